@@ -0,0 +1,321 @@
+use crate::process;
+use anyhow::{bail, Context};
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+
+/// File extension of manifests stored in the cache
+const CACHE_ENTRY_EXTENSION: &str = "yaml";
+
+/// File marking a directory as render cache
+/// Entries are only ever listed or purged in directories carrying it.
+pub const CACHE_MARKER: &str = ".helm-templexer-cache";
+
+const MARKER_CONTENT: &str = "# This directory is a render cache of helm-templexer; `helm-templexer cache purge` removes its entries.\n";
+
+/// Content-addressed store for rendered manifests
+///
+/// Entries are keyed by a hash over everything that influences the output of a deployment:
+/// the helm version, the complete command (helm arguments and pipe commands) and the contents
+/// of the chart directory and all value files.
+pub struct Cache {
+    /// Absolute path of the directory entries are stored in
+    dir: PathBuf,
+
+    /// Number of deployments served from the cache during this run
+    hits: Cell<usize>,
+
+    /// Number of deployments rendered and added to the cache during this run
+    misses: Cell<usize>,
+}
+
+/// Summary of the contents of a cache directory
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+impl Cache {
+    /// Open the cache in the given directory, creating it if necessary
+    /// The directory is resolved against the current working directory right away, as the
+    /// templexer switches the working directory for every configuration file.
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("can not create cache directory {:?}", dir))?;
+        let marker = dir.join(CACHE_MARKER);
+        if !marker.is_file() {
+            std::fs::write(&marker, MARKER_CONTENT)
+                .with_context(|| format!("can not write cache marker to {:?}", dir))?;
+        }
+
+        Ok(Self {
+            dir: dir.canonicalize()?,
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        })
+    }
+
+    /// Compute the key for the given helm version (output of `helm version --short`), command and input files
+    /// Make sure to switch the working directory to the config file before calling this.
+    pub fn key(
+        &self,
+        helm_version: &str,
+        command: &[String],
+        inputs: &[PathBuf],
+    ) -> anyhow::Result<String> {
+        let mut hasher = Sha256::new();
+
+        hasher.update(helm_version.trim().as_bytes());
+        for part in command {
+            hasher.update(b"\0");
+            hasher.update(part.as_bytes());
+        }
+
+        for input in inputs {
+            hasher.update(b"\0");
+            Self::hash_path(&mut hasher, input, input)?;
+        }
+
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
+
+    /// Look up the manifest stored under the given key; returns `None` on a cache miss
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let entry = self.entry(key);
+
+        if !entry.exists() {
+            self.misses.set(self.misses.get() + 1);
+            return Ok(None);
+        }
+
+        debug!("cache hit {}", key);
+        let manifest = std::fs::read(&entry).context("can not read manifest from cache")?;
+        self.hits.set(self.hits.get() + 1);
+
+        Ok(Some(manifest))
+    }
+
+    /// Store the given manifest under the given key
+    pub fn put(&self, key: &str, manifest: &[u8]) -> anyhow::Result<()> {
+        debug!("storing manifest in cache as {}", key);
+        process::write_atomically(&self.entry(key), manifest)
+            .context("can not write manifest to cache")?;
+
+        Ok(())
+    }
+
+    /// Number of cache hits and misses since the cache was opened
+    pub fn hits_and_misses(&self) -> (usize, usize) {
+        (self.hits.get(), self.misses.get())
+    }
+
+    /// Count the entries in the given cache directory and their total size
+    pub fn stats(dir: &Path) -> anyhow::Result<CacheStats> {
+        let mut stats = CacheStats {
+            entries: 0,
+            bytes: 0,
+        };
+
+        for entry in Self::entries(dir)? {
+            stats.entries += 1;
+            stats.bytes += entry.metadata()?.len();
+        }
+
+        Ok(stats)
+    }
+
+    /// Remove all entries from the given cache directory; returns the number of removed entries
+    /// Only files written by the cache are removed, so the directory itself is left in place.
+    pub fn purge(dir: &Path) -> anyhow::Result<usize> {
+        let entries = Self::entries(dir)?;
+
+        for entry in &entries {
+            std::fs::remove_file(entry)?;
+        }
+
+        Ok(entries.len())
+    }
+
+    /// Path of the file an entry with the given key is stored in
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension(CACHE_ENTRY_EXTENSION)
+    }
+
+    /// List all entries in the given cache directory
+    /// Fails if the directory was not created by `Cache::open`, so that a mistyped directory is
+    /// never mistaken for a cache; only files named after a key count as entries.
+    fn entries(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        if !dir.join(CACHE_MARKER).is_file() {
+            bail!(
+                "{:?} is not a render cache; it has no {} file",
+                dir,
+                CACHE_MARKER
+            );
+        }
+
+        let mut entries = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file()
+                && path.extension().and_then(|e| e.to_str()) == Some(CACHE_ENTRY_EXTENSION)
+                && path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(is_key)
+            {
+                entries.push(path);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Feed the given file, or all files in the given directory, into the hasher
+    /// Directory entries are sorted to keep the hash stable; paths are hashed relative to `base`
+    fn hash_path(hasher: &mut Sha256, base: &Path, path: &Path) -> anyhow::Result<()> {
+        if path.is_dir() {
+            let mut children = std::fs::read_dir(path)
+                .with_context(|| format!("can not read directory {:?}", path))?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            children.sort();
+
+            for child in children {
+                Self::hash_path(hasher, base, &child)?;
+            }
+
+            return Ok(());
+        }
+
+        let relative = path.strip_prefix(base).unwrap_or(path);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+        hasher.update(std::fs::read(path).with_context(|| format!("can not read {:?}", path))?);
+
+        Ok(())
+    }
+}
+
+/// Whether the given name is a key as computed by `Cache::key`, i.e. a hex encoded SHA-256
+fn is_key(name: &str) -> bool {
+    name.len() == 64 && name.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempDir;
+    use pretty_assertions::assert_eq;
+
+    const HELM_VERSION: &str = "v3.12.0+gc9f554d";
+
+    fn get_cache() -> Cache {
+        Cache {
+            dir: Default::default(),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    fn command(cmd: &str) -> Vec<String> {
+        cmd.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn key_is_stable() {
+        let cache = get_cache();
+        let cmd = command("helm template my-app tests/data/nginx-chart");
+        let inputs = vec![PathBuf::from("tests/data/nginx-chart")];
+
+        assert_eq!(
+            cache.key(HELM_VERSION, &cmd, &inputs).unwrap(),
+            cache.key(HELM_VERSION, &cmd, &inputs).unwrap()
+        );
+    }
+
+    #[test]
+    fn key_depends_on_command_and_helm_version() {
+        let cache = get_cache();
+        let inputs = vec![PathBuf::from("tests/data/nginx-chart/values/edge.yaml")];
+        let key = cache
+            .key(
+                HELM_VERSION,
+                &command("helm template my-app chart"),
+                &inputs,
+            )
+            .unwrap();
+
+        assert_ne!(
+            key,
+            cache
+                .key(
+                    HELM_VERSION,
+                    &command("helm template my-app chart | kbld -f -"),
+                    &inputs
+                )
+                .unwrap()
+        );
+
+        assert_ne!(
+            key,
+            cache
+                .key(
+                    "v3.13.0+g825e86f",
+                    &command("helm template my-app chart"),
+                    &inputs
+                )
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn key_depends_on_input_contents() {
+        let cache = get_cache();
+        let cmd = command("helm template my-app chart --values=values.yaml");
+
+        assert_ne!(
+            cache
+                .key(
+                    HELM_VERSION,
+                    &cmd,
+                    &[PathBuf::from("tests/data/nginx-chart/values/edge.yaml")]
+                )
+                .unwrap(),
+            cache
+                .key(
+                    HELM_VERSION,
+                    &cmd,
+                    &[PathBuf::from("tests/data/nginx-chart/values.yaml")]
+                )
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn only_entries_of_marked_directories_are_purged() -> anyhow::Result<()> {
+        let temp = TempDir::new("cache-marker")?;
+        let dir = temp.path();
+        temp.write("values.yaml", "replicas: 1\n")?;
+
+        assert!(Cache::purge(dir).is_err());
+        assert!(Cache::stats(dir).is_err());
+
+        let cache = Cache::open(dir)?;
+        let key = cache.key(HELM_VERSION, &command("helm template"), &[])?;
+        cache.put(&key, b"kind: Service\n")?;
+
+        assert_eq!(1, Cache::purge(dir)?);
+        assert!(!cache.entry(&key).exists());
+        assert!(dir.join("values.yaml").exists());
+
+        Ok(())
+    }
+}
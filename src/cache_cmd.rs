@@ -0,0 +1,38 @@
+use crate::cache::Cache;
+use crate::{CacheAction, CacheCmdOpts};
+use log::info;
+
+/// The cache sub command allows for inspecting and purging the render cache used by
+/// `render --cache-dir`.
+pub struct CacheCmd {
+    opts: CacheCmdOpts,
+}
+
+impl CacheCmd {
+    /// Create sub command struct to operate on the render cache
+    pub fn new(opts: CacheCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to run the requested cache operation
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("cache options: {:?}", self.opts);
+
+        match &self.opts.action {
+            CacheAction::Stats { cache_dir } => {
+                let stats = Cache::stats(cache_dir)?;
+                println!("entries: {}", stats.entries);
+                println!("size: {} bytes", stats.bytes);
+            }
+            CacheAction::Purge { cache_dir } => {
+                let removed = Cache::purge(cache_dir)?;
+                info!(
+                    "removed {} cached manifest(s) from {:?}",
+                    removed, cache_dir
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use crate::config::{Config, Deployment};
+use anyhow::{bail, Context};
+use log::debug;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use subprocess::{Exec, Redirection};
+
+/// Set of files which changed in the local git repository relative to a base revision
+/// Used to determine which deployments are affected by a change and need to be rendered
+pub struct ChangeSet {
+    /// Absolute paths of all changed files, including untracked ones
+    files: BTreeSet<PathBuf>,
+}
+
+impl ChangeSet {
+    /// Ask `git` for all files that changed since the merge base of `base` and `HEAD`
+    ///
+    /// Uncommitted and untracked (but not ignored) files are included, so that the result
+    /// matches what would be rendered from the current working tree.
+    pub fn from_git(base: &str) -> anyhow::Result<Self> {
+        let root = PathBuf::from(Self::git(&["rev-parse", "--show-toplevel"], None)?.trim());
+        debug!("git repository root discovered as {:?}", root);
+
+        let merge_base = Self::git(&["merge-base", base, "HEAD"], Some(&root))
+            .with_context(|| format!("failed to determine merge base of {:?} and HEAD", base))?;
+        let merge_base = merge_base.trim();
+        debug!("comparing working tree against merge base {}", merge_base);
+
+        let changed = Self::git(&["diff", "--name-only", merge_base, "--"], Some(&root))?;
+        let untracked = Self::git(
+            &["ls-files", "--others", "--exclude-standard", "--full-name"],
+            Some(&root),
+        )?;
+
+        let files: BTreeSet<PathBuf> = changed
+            .lines()
+            .chain(untracked.lines())
+            .filter(|l| !l.is_empty())
+            .map(|l| root.join(l))
+            .collect();
+
+        debug!("changed files: {:#?}", files);
+
+        Ok(Self { files })
+    }
+
+    /// Create a change set from a list of absolute paths, e.g. as reported by the watcher
+    pub fn from_files(files: BTreeSet<PathBuf>) -> Self {
+        Self { files }
+    }
+
+    /// Explain why the given deployment needs to be rendered; an empty result means it is unaffected
+    ///
+    /// A deployment is affected if the configuration file itself, any file inside the chart
+    /// directory or any of the value files it uses changed.
+    /// Value files which live inside the chart directory only affect the deployments using them.
+    /// Make sure to switch the working directory to the config file before calling this.
+    pub fn reasons(&self, cfg: &Config, deployment: &Deployment) -> anyhow::Result<Vec<String>> {
+        let mut reasons = vec![];
+
+        let config_file = cfg.original_working_directory.join(&cfg.config_file);
+        if self.files.contains(&Self::canonicalize(&config_file)?) {
+            reasons.push(format!("configuration file {:?} changed", cfg.config_file));
+        }
+
+        let mut all_values = BTreeSet::new();
+        let values = cfg.deployments.iter().filter_map(|d| d.values.as_ref());
+        for v in cfg.values.iter().chain(values).flatten() {
+            // disabled deployments are not validated and may reference files which do not exist
+            if let Ok(v) = v.canonicalize() {
+                all_values.insert(v);
+            }
+        }
+
+        let chart = Self::canonicalize(&cfg.chart)?;
+        let chart_files = self
+            .files
+            .iter()
+            .filter(|f| f.starts_with(&chart) && !all_values.contains(*f));
+        for f in chart_files {
+            reasons.push(format!(
+                "chart {:?} changed: {:?}",
+                cfg.chart,
+                f.strip_prefix(&chart).unwrap_or(f)
+            ));
+        }
+
+        let values = cfg.values.iter().chain(deployment.values.iter()).flatten();
+        for v in values {
+            if self.files.contains(&Self::canonicalize(v)?) {
+                reasons.push(format!("values file {:?} changed", v));
+            }
+        }
+
+        Ok(reasons)
+    }
+
+    /// Resolve the given path, relative to the current working directory, to an absolute path
+    fn canonicalize(path: &Path) -> anyhow::Result<PathBuf> {
+        path.canonicalize()
+            .with_context(|| format!("failed to resolve absolute path of {:?}", path))
+    }
+
+    /// Run `git` with the given arguments and return its stdout
+    fn git(args: &[&str], cwd: Option<&Path>) -> anyhow::Result<String> {
+        let mut exec = Exec::cmd("git")
+            .args(args)
+            .stdout(Redirection::Pipe)
+            .stderr(Redirection::Pipe);
+
+        if let Some(cwd) = cwd {
+            exec = exec.cwd(cwd);
+        }
+
+        let result = exec.capture().context("failed to run git")?;
+
+        if !result.exit_status.success() {
+            bail!(
+                "failed while running:\n    git {}\n\nstderr:\n    {}",
+                args.join(" "),
+                result.stderr_str()
+            );
+        }
+
+        Ok(result.stdout_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn get_config() -> Config {
+        Config {
+            version: "v2".to_string(),
+            enabled: Some(true),
+            helm: None,
+            chart: PathBuf::from("tests/data/nginx-chart"),
+            namespace: None,
+            release_name: "my-app".to_string(),
+            output_path: PathBuf::from("manifests"),
+            additional_options: None,
+            values: Some(vec![PathBuf::from(
+                "tests/data/nginx-chart/values/default.yaml",
+            )]),
+            pipe: None,
+            timeout: None,
+            transforms: None,
+            kube_version: None,
+            crd_schemas: None,
+            policies: None,
+            deployments: vec![],
+            original_working_directory: std::env::current_dir().unwrap(),
+            config_file: PathBuf::from("tests/data/config_example.yaml"),
+        }
+    }
+
+    fn get_deployment(name: &str, values: &str) -> Deployment {
+        Deployment {
+            name: name.to_string(),
+            enabled: None,
+            release_name: None,
+            additional_options: None,
+            values: Some(vec![PathBuf::from(values)]),
+            pipe: None,
+            timeout: None,
+            transforms: None,
+            kube_version: None,
+            policy_exceptions: None,
+        }
+    }
+
+    fn get_change_set(files: &[&str]) -> ChangeSet {
+        ChangeSet {
+            files: files
+                .iter()
+                .map(|f| PathBuf::from(f).canonicalize().unwrap())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn unrelated_changes_do_not_affect_deployments() {
+        let cfg = get_config();
+        let edge = get_deployment("edge", "tests/data/nginx-chart/values/edge.yaml");
+        let changes = get_change_set(&["README.md"]);
+
+        assert!(changes.reasons(&cfg, &edge).unwrap().is_empty());
+    }
+
+    #[test]
+    fn changed_deployment_values_only_affect_that_deployment() {
+        let mut cfg = get_config();
+        let edge = get_deployment("edge", "tests/data/nginx-chart/values/edge.yaml");
+        let prod = get_deployment("prod", "tests/data/nginx-chart/values/prod.yaml");
+        cfg.deployments = vec![
+            get_deployment("edge", "tests/data/nginx-chart/values/edge.yaml"),
+            get_deployment("prod", "tests/data/nginx-chart/values/prod.yaml"),
+        ];
+        let changes = get_change_set(&["tests/data/nginx-chart/values/prod.yaml"]);
+
+        assert!(changes.reasons(&cfg, &edge).unwrap().is_empty());
+        assert_eq!(
+            vec![r#"values file "tests/data/nginx-chart/values/prod.yaml" changed"#],
+            changes.reasons(&cfg, &prod).unwrap()
+        );
+    }
+
+    #[test]
+    fn changed_chart_files_affect_all_deployments() {
+        let cfg = get_config();
+        let edge = get_deployment("edge", "tests/data/nginx-chart/values/edge.yaml");
+        let changes = get_change_set(&["tests/data/nginx-chart/templates/service.yaml"]);
+
+        assert_eq!(
+            vec![r#"chart "tests/data/nginx-chart" changed: "templates/service.yaml""#],
+            changes.reasons(&cfg, &edge).unwrap()
+        );
+    }
+
+    #[test]
+    fn changed_config_file_affects_all_deployments() {
+        let cfg = get_config();
+        let edge = get_deployment("edge", "tests/data/nginx-chart/values/edge.yaml");
+        let changes = get_change_set(&["tests/data/config_example.yaml"]);
+
+        assert_eq!(
+            vec![r#"configuration file "tests/data/config_example.yaml" changed"#],
+            changes.reasons(&cfg, &edge).unwrap()
+        );
+    }
+}
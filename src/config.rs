@@ -1,10 +1,57 @@
+use crate::policy::{Policy, PolicyException};
+use crate::process;
+use crate::transform::Transforms;
 use anyhow::{anyhow, bail};
-use log::info;
+use log::{debug, info, warn};
 use serde::Deserialize;
 use std::path::Path;
 use std::path::PathBuf;
 
-#[derive(Deserialize, Debug)]
+mod v2;
+
+/// Schema versions configuration files can be written in, oldest first
+/// Files using any but the latest version are loaded, but trigger a deprecation warning.
+pub const SCHEMA_VERSIONS: [&str; 1] = ["v2"];
+
+const LATEST_SCHEMA_VERSION: &str = SCHEMA_VERSIONS[SCHEMA_VERSIONS.len() - 1];
+
+/// Keys of a configuration file in canonical order, matching the fields of `Config`
+pub const CONFIG_KEYS: [&str; 16] = [
+    "version",
+    "enabled",
+    "helm",
+    "chart",
+    "namespace",
+    "release_name",
+    "output_path",
+    "additional_options",
+    "values",
+    "pipe",
+    "timeout",
+    "transforms",
+    "kube_version",
+    "crd_schemas",
+    "policies",
+    "deployments",
+];
+
+/// Keys of a deployment in canonical order, matching the fields of `Deployment`
+pub const DEPLOYMENT_KEYS: [&str; 10] = [
+    "name",
+    "enabled",
+    "release_name",
+    "additional_options",
+    "values",
+    "pipe",
+    "timeout",
+    "transforms",
+    "kube_version",
+    "policy_exceptions",
+];
+
+/// Configuration as used internally, independent of the schema version of the file
+/// Each schema version has its own structs in a sub module, which are converted into this one.
+#[derive(Debug)]
 pub struct Config {
     /// Schema version to use
     pub version: String,
@@ -12,6 +59,9 @@ pub struct Config {
     /// Activate/deactivate rendering of contained deployments
     pub enabled: Option<bool>,
 
+    /// Helm binary and version to render with
+    pub helm: Option<Helm>,
+
     /// Chart to use
     pub chart: PathBuf,
 
@@ -31,17 +81,37 @@ pub struct Config {
     /// Value files to pass via `--values`
     pub values: Option<Vec<PathBuf>>,
 
+    /// Commands to pipe the manifest of every deployment through before writing it
+    pub pipe: Option<Vec<String>>,
+
+    /// Time after which `helm` and its pipe commands are aborted, e.g. `90s` or `5m`
+    pub timeout: Option<String>,
+
+    /// Built-in transforms to apply to the manifests of all deployments
+    pub transforms: Option<Transforms>,
+
+    /// Kubernetes version to validate manifests against when using `--validate-manifests`
+    pub kube_version: Option<String>,
+
+    /// Directories containing JSON schemas of custom resources, used by `--validate-manifests`
+    pub crd_schemas: Option<Vec<PathBuf>>,
+
+    /// Rules every rendered resource has to satisfy
+    pub policies: Option<Vec<Policy>>,
+
     /// List of deployments to render given Chart
     pub deployments: Vec<Deployment>,
 
     /// Utility field to store the working directory the templxeer started in
     /// Used to return to the origin after processing each configuration file, as we switch
     /// working directory every time
-    #[serde(skip)]
     pub original_working_directory: PathBuf,
+
+    /// Utility field to store the path of the configuration file as it was passed to the templexer
+    pub config_file: PathBuf,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Debug)]
 pub struct Deployment {
     /// Name of the deployment, used to create the output path
     pub name: String,
@@ -57,6 +127,75 @@ pub struct Deployment {
 
     /// Append value files to the top level value files
     pub values: Option<Vec<PathBuf>>,
+
+    /// Commands to pipe the manifest through after the top level pipe commands
+    pub pipe: Option<Vec<String>>,
+
+    /// Override the top level timeout
+    pub timeout: Option<String>,
+
+    /// Transforms to combine with the top level transforms
+    pub transforms: Option<Transforms>,
+
+    /// Override the top level Kubernetes version
+    pub kube_version: Option<String>,
+
+    /// Exempt resources of this deployment from policies
+    pub policy_exceptions: Option<Vec<PolicyException>>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Helm {
+    /// Name or path of the helm binary to call; paths are relative to the configuration file
+    pub binary: Option<String>,
+
+    /// Version requirement the helm binary has to satisfy, e.g. `>=3.8, <4`
+    pub version: Option<String>,
+}
+
+/// Validation failure caused by a specific field of the configuration file
+/// The field is given as a path like `deployments[1].values[0]`, so that it can be located in the file.
+#[derive(Debug)]
+pub struct InvalidField {
+    pub field: String,
+    pub message: String,
+}
+
+impl InvalidField {
+    fn error(field: impl Into<String>, message: impl Into<String>) -> anyhow::Error {
+        anyhow::Error::new(Self {
+            field: field.into(),
+            message: message.into(),
+        })
+    }
+}
+
+impl std::fmt::Display for InvalidField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for InvalidField {}
+
+/// Only the version of a configuration file, to pick the schema to deserialize it with
+#[derive(Deserialize)]
+struct Versioned {
+    version: Option<String>,
+}
+
+/// Warning to emit for files using the given schema version, if it is not the latest one
+fn deprecation(version: &str, versions: &[&str]) -> Option<String> {
+    let latest = versions.last()?;
+    if version == *latest || !versions.contains(&version) {
+        return None;
+    }
+
+    Some(format!(
+        "schema version {} is deprecated and will be removed in a future release; use {} instead",
+        version, latest
+    ))
 }
 
 #[derive(Default)]
@@ -72,13 +211,64 @@ impl Config {
         Self::check_file_exists_and_readable(file.as_ref())?;
 
         let cfg = std::fs::read_to_string(&file)?;
-        let mut cfg = serde_yaml::from_str::<Config>(&cfg)
+        let mut cfg = Self::parse(&cfg)
             .map_err(|err| format_serde_error::SerdeError::new(cfg.clone(), err))?;
+        if let Some(warning) = deprecation(&cfg.version, &SCHEMA_VERSIONS) {
+            warn!("{}: {}", file.as_ref().display(), warning);
+        }
         cfg.original_working_directory = std::env::current_dir()?;
+        cfg.config_file = file.as_ref().to_path_buf();
+
+        Ok(cfg)
+    }
+
+    /// Release name of the given deployment; the deployment's own takes precedence
+    pub fn release_name<'a>(&'a self, deployment: &'a Deployment) -> &'a str {
+        deployment
+            .release_name
+            .as_ref()
+            .unwrap_or(&self.release_name)
+    }
+
+    /// Deserialize a configuration using the schema of its version
+    /// Unknown versions are parsed using the latest schema, so that validation can point them out.
+    pub fn parse(source: &str) -> Result<Config, serde_yaml::Error> {
+        let version = serde_yaml::from_str::<Versioned>(source)
+            .ok()
+            .and_then(|v| v.version);
+
+        let schema = match version.as_deref() {
+            Some(version) if SCHEMA_VERSIONS.contains(&version) => version,
+            unknown => {
+                debug!(
+                    "unknown schema version {:?}; parsing it as {}",
+                    unknown, LATEST_SCHEMA_VERSION
+                );
+                LATEST_SCHEMA_VERSION
+            }
+        };
+
+        // each schema version gets an arm, converting its structs into the internal model
+        let cfg = match schema {
+            "v2" => serde_yaml::from_str::<v2::Config>(source)?.into(),
+            _ => unreachable!("schema version {} has no parser", schema),
+        };
 
         Ok(cfg)
     }
 
+    /// Path the manifest of the given deployment is written to, relative to the config file
+    pub fn manifest_path(&self, deployment: &Deployment) -> PathBuf {
+        let mut path = self
+            .output_path
+            .join(&deployment.name)
+            .join(self.release_name(deployment))
+            .join("manifest");
+        path.set_extension("yaml");
+
+        path
+    }
+
     /// Change the working directory to the place where the config file is, so that all
     /// paths are relative to the config file instead of the location where the templexer is called from
     pub fn switch_working_directory(&self, config_file: &Path) -> anyhow::Result<&Self> {
@@ -123,19 +313,44 @@ impl Config {
     pub fn validate(&self, opts: &ValidationOpts) -> anyhow::Result<&Self> {
         if let Some(enabled) = self.enabled {
             if !enabled && opts.skip_disabled {
-                info!("Skipped validation of disabled file");
+                match &opts.config_file {
+                    Some(file) => info!("Skipped validation of disabled file {:?}", file),
+                    None => info!("Skipped validation of disabled file"),
+                }
                 return Ok(self);
             }
         }
 
-        self.check_chart_exists_and_readable()?;
-        self.check_value_files_exist_and_readable()?;
-        self.check_schema_version()?;
-        self.check_if_at_least_one_deployment_is_enabled()?;
+        for (_, result) in self.checks() {
+            result?;
+        }
 
         Ok(self)
     }
 
+    /// Run all validation checks without stopping at the first failure
+    /// Returns a short description of each check along with its outcome, in order of execution.
+    pub fn checks(&self) -> Vec<(&'static str, anyhow::Result<()>)> {
+        vec![
+            ("chart exists", self.check_chart_exists_and_readable()),
+            (
+                "values files exist",
+                self.check_value_files_exist_and_readable(),
+            ),
+            ("schema version", self.check_schema_version()),
+            (
+                "helm version constraint",
+                self.check_helm_version_constraint(),
+            ),
+            ("timeouts", self.check_timeouts()),
+            ("policies", self.check_policies()),
+            (
+                "at least one deployment enabled",
+                self.check_if_at_least_one_deployment_is_enabled(),
+            ),
+        ]
+    }
+
     /// Check whether the given input file exists and is readable
     fn check_file_exists_and_readable(input_file: &Path) -> anyhow::Result<()> {
         if !input_file.exists() {
@@ -148,7 +363,10 @@ impl Config {
     /// Assert that the designated Helm chart can be found on disk
     fn check_chart_exists_and_readable(&self) -> anyhow::Result<()> {
         if !self.chart.exists() {
-            bail!("Chart {:?} does not exist or is not readable", self.chart);
+            return Err(InvalidField::error(
+                "chart",
+                format!("Chart {:?} does not exist or is not readable", self.chart),
+            ));
         }
 
         Ok(())
@@ -156,19 +374,17 @@ impl Config {
 
     /// Find all referenced value files in the given config and check if they exist
     fn check_value_files_exist_and_readable(&self) -> anyhow::Result<()> {
-        match &self.values {
-            Some(values) => Self::check_pathbuf_vec(values)?,
-            None => (),
+        if let Some(values) = &self.values {
+            Self::check_pathbuf_vec("values", values)?
         }
 
-        for deployment in &self.deployments {
+        for (i, deployment) in self.deployments.iter().enumerate() {
             if matches!(deployment.enabled, Some(enabled) if !enabled) {
                 continue;
             }
 
-            match &deployment.values {
-                Some(values) => Self::check_pathbuf_vec(values)?,
-                None => (),
+            if let Some(values) = &deployment.values {
+                Self::check_pathbuf_vec(&format!("deployments[{}].values", i), values)?
             }
         }
 
@@ -176,20 +392,105 @@ impl Config {
     }
 
     /// Helper to iterate a vector of paths and check their existence
-    fn check_pathbuf_vec(files: &[PathBuf]) -> anyhow::Result<()> {
-        for f in files {
+    /// `field` is the path of the list within the configuration file, used to report failures.
+    fn check_pathbuf_vec(field: &str, files: &[PathBuf]) -> anyhow::Result<()> {
+        for (i, f) in files.iter().enumerate() {
             if !f.exists() {
-                bail!("values file {:?} does not exist or is not readable", f)
+                return Err(InvalidField::error(
+                    format!("{}[{}]", field, i),
+                    format!("values file {:?} does not exist or is not readable", f),
+                ));
             }
         }
 
         Ok(())
     }
 
-    /// Check the given schema version; should be extended once multiple are available
+    /// Check the given schema version is one of `SCHEMA_VERSIONS`
     fn check_schema_version(&self) -> anyhow::Result<()> {
-        if self.version != "v2" {
-            bail!("invalid schema version used; only 'v2' is supported")
+        if !SCHEMA_VERSIONS.contains(&self.version.as_str()) {
+            let supported: Vec<String> =
+                SCHEMA_VERSIONS.iter().map(|v| format!("'{}'", v)).collect();
+            return Err(InvalidField::error(
+                "version",
+                format!(
+                    "invalid schema version used; only {} is supported",
+                    supported.join(" or ")
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check that the helm version constraint, if any, can be parsed
+    fn check_helm_version_constraint(&self) -> anyhow::Result<()> {
+        if let Some(Helm {
+            version: Some(version),
+            ..
+        }) = &self.helm
+        {
+            if let Err(err) = semver::VersionReq::parse(version) {
+                return Err(InvalidField::error(
+                    "helm.version",
+                    format!("invalid helm version constraint {:?}: {}", version, err),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that all timeouts, if any, can be parsed
+    fn check_timeouts(&self) -> anyhow::Result<()> {
+        let timeouts = std::iter::once(("timeout".to_string(), &self.timeout)).chain(
+            self.deployments
+                .iter()
+                .enumerate()
+                .map(|(i, d)| (format!("deployments[{}].timeout", i), &d.timeout)),
+        );
+
+        for (field, timeout) in timeouts {
+            if let Some(timeout) = timeout {
+                if let Err(err) = process::parse_timeout(timeout) {
+                    return Err(InvalidField::error(field, format!("{:#}", err)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that all policies can be evaluated and exceptions refer to existing policies
+    fn check_policies(&self) -> anyhow::Result<()> {
+        let policies = self.policies.as_deref().unwrap_or_default();
+
+        for (i, policy) in policies.iter().enumerate() {
+            if policies[..i].iter().any(|p| p.name == policy.name) {
+                return Err(InvalidField::error(
+                    format!("policies[{}].name", i),
+                    format!("policy {:?} is defined more than once", policy.name),
+                ));
+            }
+
+            if let Err((field, err)) = policy.check() {
+                return Err(InvalidField::error(
+                    format!("policies[{}].{}", i, field),
+                    format!("invalid policy {:?}: {:#}", policy.name, err),
+                ));
+            }
+        }
+
+        for (i, deployment) in self.deployments.iter().enumerate() {
+            let exceptions = deployment.policy_exceptions.iter().flatten();
+            for (j, exception) in exceptions.enumerate() {
+                if !policies.iter().any(|p| p.name == exception.policy) {
+                    return Err(InvalidField::error(
+                        format!("deployments[{}].policy_exceptions[{}].policy", i, j),
+                        format!("policy {:?} does not exist", exception.policy),
+                    ));
+                }
+            }
         }
 
         Ok(())
@@ -215,7 +516,10 @@ impl Config {
         }
 
         if all_disabled {
-            bail!("All deployments are disabled")
+            return Err(InvalidField::error(
+                "deployments",
+                "All deployments are disabled",
+            ));
         }
 
         Ok(())
@@ -232,14 +536,22 @@ mod tests {
         Config {
             version: "v2".to_string(),
             enabled: Some(true),
+            helm: None,
             chart: Default::default(),
             namespace: None,
             release_name: "".to_string(),
             output_path: Default::default(),
             additional_options: None,
             values: None,
+            pipe: None,
+            timeout: None,
+            transforms: None,
+            kube_version: None,
+            crd_schemas: None,
+            policies: None,
             deployments: vec![],
             original_working_directory: Default::default(),
+            config_file: Default::default(),
         }
     }
 
@@ -250,6 +562,11 @@ mod tests {
             release_name: None,
             additional_options: None,
             values: None,
+            pipe: None,
+            timeout: None,
+            transforms: None,
+            kube_version: None,
+            policy_exceptions: None,
         }
     }
 
@@ -337,4 +654,111 @@ mod tests {
 
         cfg.validate(&ValidationOpts::default()).unwrap();
     }
+
+    #[test]
+    fn policy_exceptions_must_refer_to_existing_policies() {
+        let mut cfg = get_config();
+        cfg.policies = Some(
+            serde_yaml::from_str(
+                r#"
+- name: no-latest-tag
+  assert:
+    - path: $..containers[*].image
+      not_matches: ":latest$"
+"#,
+            )
+            .unwrap(),
+        );
+
+        let mut deployment = get_deployment();
+        deployment.policy_exceptions = Some(
+            serde_yaml::from_str(
+                r#"
+- policy: no-latest-tag
+- policy: no-host-path
+"#,
+            )
+            .unwrap(),
+        );
+        cfg.deployments = vec![deployment];
+
+        let err = cfg.check_policies().err().unwrap();
+        let invalid = err.downcast_ref::<InvalidField>().unwrap();
+        assert_eq!("deployments[0].policy_exceptions[1].policy", invalid.field);
+        assert_eq!(
+            "policy \"no-host-path\" does not exist",
+            invalid.to_string()
+        );
+    }
+
+    #[test]
+    fn configs_are_parsed_using_the_schema_of_their_version() {
+        let cfg = Config::parse(
+            "version: v2\nchart: chart\nrelease_name: app\noutput_path: manifests\ndeployments:\n  - name: edge\n",
+        )
+        .unwrap();
+        assert_eq!("edge", cfg.deployments[0].name);
+
+        // unknown versions are left to validation to report
+        let cfg = Config::parse(
+            "version: v9\nchart: chart\nrelease_name: app\noutput_path: manifests\ndeployments: []\n",
+        )
+        .unwrap();
+        assert_eq!(
+            "invalid schema version used; only 'v2' is supported",
+            cfg.check_schema_version().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn every_schema_version_can_be_parsed() {
+        for version in SCHEMA_VERSIONS {
+            let cfg = Config::parse(&format!(
+                "version: {}\nchart: chart\nrelease_name: app\noutput_path: manifests\ndeployments: []\n",
+                version
+            ))
+            .unwrap();
+            assert_eq!(version, cfg.version);
+        }
+    }
+
+    #[test]
+    fn canonical_keys_are_fields_of_the_latest_schema() {
+        // `[{}]` fits none of the fields, so parsing only succeeds if a key is ignored as unknown
+        let config = "version: v2\nchart: chart\nrelease_name: app\noutput_path: manifests\n";
+        let deployment = "deployments:\n  - name: edge\n";
+        assert!(serde_yaml::from_str::<v2::Config>(&format!(
+            "{}unknown: [{{}}]\n{}",
+            config, deployment
+        ))
+        .is_ok());
+
+        for key in CONFIG_KEYS.iter() {
+            let source = format!("{}{}\n{}: [{{}}]\n", config, deployment, key);
+            assert!(
+                serde_yaml::from_str::<v2::Config>(&source).is_err(),
+                "{}",
+                key
+            );
+        }
+        for key in DEPLOYMENT_KEYS.iter() {
+            let source = format!("{}{}    {}: [{{}}]\n", config, deployment, key);
+            assert!(
+                serde_yaml::from_str::<v2::Config>(&source).is_err(),
+                "{}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn only_outdated_schema_versions_are_deprecated() {
+        let versions = ["v2", "v3"];
+        assert_eq!(
+            Some("schema version v2 is deprecated and will be removed in a future release; use v3 instead".to_string()),
+            deprecation("v2", &versions)
+        );
+        assert_eq!(None, deprecation("v3", &versions));
+        assert_eq!(None, deprecation("v1", &versions));
+    }
 }
@@ -0,0 +1,210 @@
+//! Schema v2 of the configuration file
+//! See `crate::config::Config` for the meaning of each field. The nested types are copies of the
+//! internal ones, so that changing the internal model never changes how v2 files are parsed.
+
+use crate::{config, policy, transform};
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub version: String,
+    pub enabled: Option<bool>,
+    pub helm: Option<Helm>,
+    pub chart: PathBuf,
+    pub namespace: Option<String>,
+    pub release_name: String,
+    pub output_path: PathBuf,
+    pub additional_options: Option<Vec<String>>,
+    pub values: Option<Vec<PathBuf>>,
+    pub pipe: Option<Vec<String>>,
+    pub timeout: Option<String>,
+    pub transforms: Option<Transforms>,
+    pub kube_version: Option<String>,
+    pub crd_schemas: Option<Vec<PathBuf>>,
+    pub policies: Option<Vec<Policy>>,
+    pub deployments: Vec<Deployment>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Deployment {
+    pub name: String,
+    pub enabled: Option<bool>,
+    pub release_name: Option<String>,
+    pub additional_options: Option<Vec<String>>,
+    pub values: Option<Vec<PathBuf>>,
+    pub pipe: Option<Vec<String>>,
+    pub timeout: Option<String>,
+    pub transforms: Option<Transforms>,
+    pub kube_version: Option<String>,
+    pub policy_exceptions: Option<Vec<PolicyException>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Helm {
+    pub binary: Option<String>,
+    pub version: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Transforms {
+    pub remove_labels: Option<Vec<String>>,
+    pub add_labels: Option<BTreeMap<String, String>>,
+    pub remove_annotations: Option<Vec<String>>,
+    pub add_annotations: Option<BTreeMap<String, String>>,
+    pub drop_comments: Option<bool>,
+    pub sort: Option<bool>,
+    pub remove_empty: Option<bool>,
+    pub normalize: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub name: String,
+    pub kinds: Option<Vec<String>>,
+    pub severity: Option<Severity>,
+    pub message: Option<String>,
+    pub assert: Vec<Assertion>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Assertion {
+    pub path: String,
+    pub exists: Option<bool>,
+    pub equals: Option<Value>,
+    pub matches: Option<String>,
+    pub not_matches: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyException {
+    pub policy: String,
+    pub resources: Option<Vec<String>>,
+}
+
+/// Convert all items of an optional list
+fn convert<T: Into<U>, U>(items: Option<Vec<T>>) -> Option<Vec<U>> {
+    items.map(|items| items.into_iter().map(Into::into).collect())
+}
+
+impl From<Config> for config::Config {
+    fn from(cfg: Config) -> Self {
+        Self {
+            version: cfg.version,
+            enabled: cfg.enabled,
+            helm: cfg.helm.map(Into::into),
+            chart: cfg.chart,
+            namespace: cfg.namespace,
+            release_name: cfg.release_name,
+            output_path: cfg.output_path,
+            additional_options: cfg.additional_options,
+            values: cfg.values,
+            pipe: cfg.pipe,
+            timeout: cfg.timeout,
+            transforms: cfg.transforms.map(Into::into),
+            kube_version: cfg.kube_version,
+            crd_schemas: cfg.crd_schemas,
+            policies: convert(cfg.policies),
+            deployments: cfg.deployments.into_iter().map(Into::into).collect(),
+            original_working_directory: Default::default(),
+            config_file: Default::default(),
+        }
+    }
+}
+
+impl From<Deployment> for config::Deployment {
+    fn from(d: Deployment) -> Self {
+        Self {
+            name: d.name,
+            enabled: d.enabled,
+            release_name: d.release_name,
+            additional_options: d.additional_options,
+            values: d.values,
+            pipe: d.pipe,
+            timeout: d.timeout,
+            transforms: d.transforms.map(Into::into),
+            kube_version: d.kube_version,
+            policy_exceptions: convert(d.policy_exceptions),
+        }
+    }
+}
+
+impl From<Helm> for config::Helm {
+    fn from(helm: Helm) -> Self {
+        Self {
+            binary: helm.binary,
+            version: helm.version,
+        }
+    }
+}
+
+impl From<Transforms> for transform::Transforms {
+    fn from(t: Transforms) -> Self {
+        Self {
+            remove_labels: t.remove_labels,
+            add_labels: t.add_labels,
+            remove_annotations: t.remove_annotations,
+            add_annotations: t.add_annotations,
+            drop_comments: t.drop_comments,
+            sort: t.sort,
+            remove_empty: t.remove_empty,
+            normalize: t.normalize,
+        }
+    }
+}
+
+impl From<Policy> for policy::Policy {
+    fn from(p: Policy) -> Self {
+        Self {
+            name: p.name,
+            kinds: p.kinds,
+            severity: p.severity.map(Into::into),
+            message: p.message,
+            assert: p.assert.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<Severity> for policy::Severity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Error => Self::Error,
+            Severity::Warning => Self::Warning,
+        }
+    }
+}
+
+impl From<Assertion> for policy::Assertion {
+    fn from(a: Assertion) -> Self {
+        Self {
+            path: a.path,
+            exists: a.exists,
+            equals: a.equals,
+            matches: a.matches,
+            not_matches: a.not_matches,
+        }
+    }
+}
+
+impl From<PolicyException> for policy::PolicyException {
+    fn from(e: PolicyException) -> Self {
+        Self {
+            policy: e.policy,
+            resources: e.resources,
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use crate::config::{Config, InvalidField};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use yaml_rust::parser::{Event, Parser};
+use yaml_rust::scanner::TScalarStyle;
+
+/// Validation error with the location in the configuration file it originates from
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    /// Configuration file as passed to the templexer
+    pub file: PathBuf,
+
+    /// 1-based line of the offending field; unknown if the file could not be parsed at all
+    pub line: Option<usize>,
+
+    /// 1-based column of the offending field
+    pub column: Option<usize>,
+
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic for an error raised while loading or validating the given file
+    /// The file is read again to determine the location, so make sure the working directory is
+    /// the one the templexer was started in.
+    pub fn from_error(file: &Path, err: &anyhow::Error) -> Self {
+        let mut diagnostic = Self {
+            file: file.to_path_buf(),
+            line: None,
+            column: None,
+            message: format!("{:#}", err),
+        };
+
+        let source = match std::fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(_) => return diagnostic,
+        };
+
+        if let Some(invalid) = err.downcast_ref::<InvalidField>() {
+            if let Some((line, column)) = locate(&source, &invalid.field) {
+                diagnostic.line = Some(line);
+                diagnostic.column = Some(column);
+            }
+            return diagnostic;
+        }
+
+        // the error printed by `Config::load` contains a code snippet; prefer serde's message
+        if let Err(err) = Config::parse(&source) {
+            diagnostic.message = err.to_string();
+            if let Some(location) = err.location() {
+                diagnostic.line = Some(location.line());
+                diagnostic.column = Some(location.column());
+            }
+        }
+
+        diagnostic
+    }
+
+    /// Format as GitHub Actions workflow command, so that the error is shown on the pull request
+    pub fn to_github(&self) -> String {
+        let mut properties = vec![format!(
+            "file={}",
+            escape_property(&self.file.display().to_string())
+        )];
+        if let Some(line) = self.line {
+            properties.push(format!("line={}", line));
+        }
+        if let Some(column) = self.column {
+            properties.push(format!("col={}", column));
+        }
+
+        format!(
+            "::error {}::{}",
+            properties.join(","),
+            escape_data(&self.message)
+        )
+    }
+}
+
+/// Build a SARIF 2.1.0 log containing the given diagnostics
+pub fn sarif(diagnostics: &[Diagnostic]) -> serde_json::Value {
+    let results: Vec<serde_json::Value> = diagnostics
+        .iter()
+        .map(|d| {
+            let mut location = json!({
+                "physicalLocation": {
+                    "artifactLocation": { "uri": d.file.display().to_string() }
+                }
+            });
+            if let Some(line) = d.line {
+                location["physicalLocation"]["region"] = json!({
+                    "startLine": line,
+                    "startColumn": d.column.unwrap_or(1),
+                });
+            }
+
+            json!({
+                "level": "error",
+                "message": { "text": d.message },
+                "locations": [location],
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "informationUri": "https://github.com/hendrikmaus/helm-templexer",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+/// Position of a container while walking the YAML event stream
+enum Frame {
+    Mapping { key: Option<String> },
+    Sequence { index: usize },
+}
+
+/// Find the 1-based line and column of the given field, e.g. `deployments[1].values[0]`
+/// Mapping entries are located at their key, sequence items at their value.
+fn locate(source: &str, field: &str) -> Option<(usize, usize)> {
+    let mut parser = Parser::new(source.chars());
+    let mut stack: Vec<Frame> = vec![];
+
+    loop {
+        let (event, marker) = parser.next().ok()?;
+        if let (Event::Scalar(name, ..), Some(Frame::Mapping { key: key @ None })) =
+            (&event, stack.last_mut())
+        {
+            *key = Some(name.clone());
+            if path(&stack) == field {
+                return Some((marker.line(), marker.col() + 1));
+            }
+            continue;
+        }
+
+        let is_node = matches!(
+            event,
+            Event::Scalar(..) | Event::MappingStart(_) | Event::SequenceStart(_) | Event::Alias(_)
+        );
+        if is_node && matches!(stack.last(), Some(Frame::Sequence { .. })) && path(&stack) == field
+        {
+            return Some((marker.line(), marker.col() + 1));
+        }
+
+        match event {
+            Event::MappingStart(_) => stack.push(Frame::Mapping { key: None }),
+            Event::SequenceStart(_) => stack.push(Frame::Sequence { index: 0 }),
+            Event::MappingEnd | Event::SequenceEnd => {
+                stack.pop();
+                value_done(&mut stack);
+            }
+            Event::Scalar(..) | Event::Alias(_) => value_done(&mut stack),
+            Event::StreamEnd => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Scalar value of a YAML document along with its location
+#[derive(Debug, PartialEq)]
+pub struct Scalar {
+    /// Field path of the value, e.g. `deployments[1].values[0]`
+    pub field: String,
+
+    pub value: String,
+    pub style: TScalarStyle,
+
+    /// 0-based character index and 1-based line the value starts at, including quotes
+    pub index: usize,
+    pub line: usize,
+}
+
+/// Collect all scalar values of the document; mapping keys are left out
+pub fn scalars(source: &str) -> anyhow::Result<Vec<Scalar>> {
+    let mut parser = Parser::new(source.chars());
+    let mut stack: Vec<Frame> = vec![];
+    let mut scalars = vec![];
+
+    loop {
+        let (event, marker) = parser.next()?;
+        match event {
+            Event::Scalar(name, ..)
+                if matches!(stack.last(), Some(Frame::Mapping { key: None })) =>
+            {
+                if let Some(Frame::Mapping { key }) = stack.last_mut() {
+                    *key = Some(name);
+                }
+            }
+            Event::Scalar(value, style, ..) => {
+                scalars.push(Scalar {
+                    field: path(&stack),
+                    value,
+                    style,
+                    index: marker.index(),
+                    line: marker.line(),
+                });
+                value_done(&mut stack);
+            }
+            Event::MappingStart(_) => stack.push(Frame::Mapping { key: None }),
+            Event::SequenceStart(_) => stack.push(Frame::Sequence { index: 0 }),
+            Event::MappingEnd | Event::SequenceEnd => {
+                stack.pop();
+                value_done(&mut stack);
+            }
+            Event::Alias(_) => value_done(&mut stack),
+            Event::StreamEnd => return Ok(scalars),
+            _ => {}
+        }
+    }
+}
+
+/// Advance the innermost container once one of its values has been consumed
+fn value_done(stack: &mut [Frame]) {
+    match stack.last_mut() {
+        Some(Frame::Mapping { key }) => *key = None,
+        Some(Frame::Sequence { index }) => *index += 1,
+        None => {}
+    }
+}
+
+/// Render the current position in the event stream as field path
+fn path(stack: &[Frame]) -> String {
+    let mut path = String::new();
+
+    for frame in stack {
+        match frame {
+            Frame::Mapping { key: Some(key) } => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+            }
+            Frame::Mapping { key: None } => {}
+            Frame::Sequence { index } => path.push_str(&format!("[{}]", index)),
+        }
+    }
+
+    path
+}
+
+/// Escape the message of a workflow command
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property value of a workflow command
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const CONFIG: &str = r#"---
+version: v2
+chart: nginx-chart
+values:
+  - values/default.yaml
+deployments:
+  - name: edge
+    values:
+      - values/edge.yaml
+  - name: prod
+    values: [values/prod.yaml, values/prod-eu.yaml]
+"#;
+
+    #[test]
+    fn fields_are_located_in_source() {
+        assert_eq!(Some((3, 1)), locate(CONFIG, "chart"));
+        assert_eq!(Some((5, 5)), locate(CONFIG, "values[0]"));
+        assert_eq!(Some((6, 1)), locate(CONFIG, "deployments"));
+        assert_eq!(Some((9, 9)), locate(CONFIG, "deployments[0].values[0]"));
+        assert_eq!(Some((11, 32)), locate(CONFIG, "deployments[1].values[1]"));
+        assert_eq!(None, locate(CONFIG, "deployments[2]"));
+    }
+
+    #[test]
+    fn github_workflow_commands_are_escaped() {
+        let diagnostic = Diagnostic {
+            file: PathBuf::from("deploy/my-app.yaml"),
+            line: Some(3),
+            column: Some(1),
+            message: "Chart \"nginx-chart\" does not exist\n100% sure".to_string(),
+        };
+
+        assert_eq!(
+            "::error file=deploy/my-app.yaml,line=3,col=1::Chart \"nginx-chart\" does not exist%0A100%25 sure",
+            diagnostic.to_github()
+        );
+    }
+
+    #[test]
+    fn sarif_results_carry_the_region() {
+        let diagnostics = vec![Diagnostic {
+            file: PathBuf::from("my-app.yaml"),
+            line: Some(2),
+            column: Some(1),
+            message: "invalid schema version used".to_string(),
+        }];
+
+        let sarif = sarif(&diagnostics);
+        let result = &sarif["runs"][0]["results"][0];
+
+        assert_eq!("2.1.0", sarif["version"]);
+        assert_eq!("invalid schema version used", result["message"]["text"]);
+        assert_eq!(
+            json!({
+                "artifactLocation": { "uri": "my-app.yaml" },
+                "region": { "startLine": 2, "startColumn": 1 }
+            }),
+            result["locations"][0]["physicalLocation"]
+        );
+    }
+}
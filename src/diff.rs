@@ -0,0 +1,80 @@
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// How a value differs between two documents
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Single value which differs between two documents
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Difference {
+    /// Key path of the value, e.g. `image.tag`
+    pub path: String,
+
+    pub change: Change,
+
+    /// Value of the first document; not set if the value was added
+    pub from: Option<Value>,
+
+    /// Value of the second document; not set if the value was removed
+    pub to: Option<Value>,
+}
+
+impl fmt::Display for Difference {
+    /// Format like `~ image.tag: "1.24" -> "1.25"`, with values as inline JSON
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inline = |v: &Option<Value>| serde_json::to_string(v).unwrap_or_default();
+        match self.change {
+            Change::Added => write!(f, "+ {}: {}", self.path, inline(&self.to)),
+            Change::Removed => write!(f, "- {}: {}", self.path, inline(&self.from)),
+            Change::Changed => write!(
+                f,
+                "~ {}: {} -> {}",
+                self.path,
+                inline(&self.from),
+                inline(&self.to)
+            ),
+        }
+    }
+}
+
+/// Compare two flattened documents key path by key path; differences are sorted by path
+pub fn compare(from: BTreeMap<String, Value>, mut to: BTreeMap<String, Value>) -> Vec<Difference> {
+    let mut differences = vec![];
+
+    for (path, value) in from {
+        match to.remove(&path) {
+            None => differences.push(Difference {
+                path,
+                change: Change::Removed,
+                from: Some(value),
+                to: None,
+            }),
+            Some(other) if other != value => differences.push(Difference {
+                path,
+                change: Change::Changed,
+                from: Some(value),
+                to: Some(other),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (path, value) in to {
+        differences.push(Difference {
+            path,
+            change: Change::Added,
+            from: None,
+            to: Some(value),
+        });
+    }
+
+    differences.sort_by(|a, b| a.path.cmp(&b.path));
+    differences
+}
@@ -0,0 +1,361 @@
+use crate::config::{CONFIG_KEYS, DEPLOYMENT_KEYS};
+use crate::process;
+use crate::FmtCmdOpts;
+use anyhow::{bail, Context};
+use log::{info, warn};
+
+/// The fmt sub command rewrites configuration files into the canonical key order.
+pub struct FmtCmd {
+    opts: FmtCmdOpts,
+}
+
+/// Entry of a block mapping: the lines of a key, along with the comments directly above it and
+/// the blank lines separating it from the entry above
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    lines: Vec<String>,
+}
+
+/// Block mapping split into its entries
+#[derive(Debug, Default)]
+struct Mapping {
+    /// Lines before the first entry, e.g. the document start and leading comments
+    head: Vec<String>,
+
+    entries: Vec<Entry>,
+
+    /// Lines after the last entry, e.g. trailing comments
+    tail: Vec<String>,
+}
+
+impl FmtCmd {
+    /// Create sub command struct to format the given input file(s)
+    pub fn new(opts: FmtCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to format the configuration files
+    /// will rewrite all files in place, or only report them in check mode
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("fmt options: {:?}", self.opts);
+
+        let mut unformatted = 0;
+        for file in &self.opts.input_files {
+            let source = std::fs::read_to_string(file)
+                .with_context(|| format!("can not read {:?}", file))?;
+            let formatted =
+                format(&source).with_context(|| format!("can not format {:?}", file))?;
+            if formatted == source {
+                continue;
+            }
+
+            if self.opts.check {
+                warn!("{}: not formatted", file.display());
+                unformatted += 1;
+            } else {
+                process::write_atomically(file, formatted.as_bytes())?;
+                info!("{}: formatted", file.display());
+            }
+        }
+
+        if unformatted > 0 {
+            bail!(
+                "{} file(s) need formatting; run `helm-templexer fmt` to fix them",
+                unformatted
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Order the keys of the configuration and its deployments like the fields of `Config` and
+/// `Deployment`, keeping comments and the formatting of values
+/// Unknown keys are moved after the known ones. Fails rather than changing the content.
+fn format(source: &str) -> anyhow::Result<String> {
+    let original: serde_yaml::Value = serde_yaml::from_str(source)?;
+
+    let lines: Vec<String> = source.lines().map(String::from).collect();
+    let mut mapping = Mapping::parse(lines, 0, is_key);
+    for entry in &mut mapping.entries {
+        if entry.key == "deployments" {
+            entry.lines = format_deployments(std::mem::take(&mut entry.lines));
+        }
+    }
+    mapping.sort(&CONFIG_KEYS);
+
+    let mut formatted = mapping.into_lines().join("\n");
+    if source.ends_with('\n') {
+        formatted.push('\n');
+    }
+
+    let result: serde_yaml::Value = serde_yaml::from_str(&formatted)?;
+    if result != original {
+        bail!("the file can not be formatted without changing its content; only block style mappings are supported");
+    }
+
+    Ok(formatted)
+}
+
+/// Order the keys of each item of the `deployments` entry
+fn format_deployments(lines: Vec<String>) -> Vec<String> {
+    let key_line = lines
+        .iter()
+        .position(|l| !l.trim().is_empty() && !is_comment(l))
+        .unwrap_or(0);
+    let dash = match lines[key_line + 1..]
+        .iter()
+        .find(|l| !l.trim().is_empty() && !is_comment(l))
+    {
+        Some(line) if line.trim_start().starts_with('-') => indentation(line),
+        _ => return lines,
+    };
+
+    let mut result = lines[..=key_line].to_vec();
+    let items = Mapping::parse(lines[key_line + 1..].to_vec(), dash, is_item);
+    result.extend(items.head);
+    for item in items.entries {
+        result.extend(format_item(item.lines, dash));
+    }
+    result.extend(items.tail);
+
+    result
+}
+
+/// Order the keys of a single deployment, given as the lines of its sequence item
+fn format_item(lines: Vec<String>, dash: usize) -> Vec<String> {
+    let mut comments = vec![];
+    let mut rest = vec![];
+    for line in lines {
+        match is_item(&line, dash) {
+            // turn the item into a plain mapping, by replacing the dash with a space
+            Some(_) if rest.is_empty() => {
+                rest.push(format!("{} {}", &line[..dash], &line[dash + 1..]))
+            }
+            _ if rest.is_empty() => comments.push(line),
+            _ => rest.push(line),
+        }
+    }
+
+    let indent = match rest.iter().find(|l| !l.trim().is_empty() && !is_comment(l)) {
+        Some(line) => indentation(line),
+        None => return comments.into_iter().chain(rest).collect(),
+    };
+    let mut mapping = Mapping::parse(rest, indent, is_key);
+    mapping.sort(&DEPLOYMENT_KEYS);
+
+    let mut dashed = false;
+    for line in mapping.into_lines() {
+        if !dashed && !line.trim().is_empty() && !is_comment(&line) {
+            comments.push(format!("{}-{}", &line[..dash], &line[dash + 1..]));
+            dashed = true;
+        } else if !dashed && is_comment(&line) {
+            // comments moved above the first key now precede the item
+            comments.push(format!("{}{}", " ".repeat(dash), line.trim_start()));
+        } else {
+            comments.push(line);
+        }
+    }
+
+    comments
+}
+
+impl Mapping {
+    /// Split the lines of a block mapping at the given indentation into its entries
+    /// Comments directly above an entry belong to it, as do the blank lines above those; all
+    /// other lines belong to the entry above. A comment separated from the first entry by a blank
+    /// line is considered a header and stays at the top.
+    fn parse(
+        lines: Vec<String>,
+        indent: usize,
+        starts_entry: fn(&str, usize) -> Option<String>,
+    ) -> Self {
+        let mut mapping = Mapping::default();
+        let mut pending: Vec<String> = vec![];
+
+        for line in lines {
+            let key = match starts_entry(&line, indent) {
+                Some(key) => key,
+                None => {
+                    pending.push(line);
+                    continue;
+                }
+            };
+
+            let comments = pending
+                .iter()
+                .rev()
+                .take_while(|l| is_comment(l) && indentation(l) <= indent)
+                .count();
+            // blank lines above the first entry separate it from the head, not from an entry
+            let blanks = match mapping.entries.is_empty() {
+                true => 0,
+                false => pending
+                    .iter()
+                    .rev()
+                    .skip(comments)
+                    .take_while(|l| l.trim().is_empty())
+                    .count(),
+            };
+            let mut lines = pending.split_off(pending.len() - comments - blanks);
+            match mapping.entries.last_mut() {
+                Some(entry) => entry.lines.append(&mut pending),
+                None => mapping.head.append(&mut pending),
+            }
+
+            lines.push(line);
+            mapping.entries.push(Entry { key, lines });
+        }
+
+        // trailing blank lines and comments not indented deeper than the keys end the mapping
+        let nested = pending
+            .iter()
+            .rposition(|l| !l.trim().is_empty() && (!is_comment(l) || indentation(l) > indent))
+            .map_or(0, |i| i + 1);
+        match mapping.entries.last_mut() {
+            Some(entry) => {
+                let tail = pending.split_off(nested);
+                entry.lines.append(&mut pending);
+                mapping.tail = tail;
+            }
+            None => mapping.head.append(&mut pending),
+        }
+        mapping
+    }
+
+    /// Order the entries by the given keys; unknown keys keep their order after the known ones
+    fn sort(&mut self, keys: &[&str]) {
+        self.entries
+            .sort_by_key(|e| keys.iter().position(|k| *k == e.key).unwrap_or(keys.len()));
+    }
+
+    fn into_lines(self) -> Vec<String> {
+        let mut lines = self.head;
+        for (i, entry) in self.entries.into_iter().enumerate() {
+            // the first entry is not separated from the head
+            let skip = match i {
+                0 => entry
+                    .lines
+                    .iter()
+                    .take_while(|l| l.trim().is_empty())
+                    .count(),
+                _ => 0,
+            };
+            lines.extend(entry.lines.into_iter().skip(skip));
+        }
+        lines.extend(self.tail);
+
+        lines
+    }
+}
+
+/// Key of the line, if it starts an entry of a block mapping at the given indentation
+fn is_key(line: &str, indent: usize) -> Option<String> {
+    if indentation(line) != indent || line.trim().is_empty() || is_comment(line) {
+        return None;
+    }
+
+    let content = line.trim();
+    if content.starts_with('-') || content.starts_with("...") {
+        return None;
+    }
+    let key = content
+        .split_once(": ")
+        .map(|(key, _)| key)
+        .or_else(|| content.split_once(" #").map(|(key, _)| key.trim_end()))
+        .unwrap_or(content)
+        .trim_end_matches(':');
+
+    Some(key.trim_matches(|c| c == '"' || c == '\'').to_string())
+}
+
+/// Whether the line starts an item of a block sequence at the given indentation
+fn is_item(line: &str, indent: usize) -> Option<String> {
+    let content = line.get(indent..)?;
+    (indentation(line) == indent && (content == "-" || content.starts_with("- "))).then(String::new)
+}
+
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn keys_are_ordered_like_the_config_fields() {
+        let source = r#"---
+# owned by the platform team
+
+deployments:
+  - values:
+      - values/edge.yaml # edge only
+    # the name doubles as output directory
+    name: edge
+
+  - name: prod
+release_name: "app"
+
+# rendered by CI
+output_path: manifests
+values: [values/default.yaml]
+chart: chart
+version: v2
+"#;
+
+        assert_eq!(
+            r#"---
+# owned by the platform team
+
+version: v2
+chart: chart
+release_name: "app"
+
+# rendered by CI
+output_path: manifests
+values: [values/default.yaml]
+deployments:
+  # the name doubles as output directory
+  - name: edge
+    values:
+      - values/edge.yaml # edge only
+
+  - name: prod
+"#,
+            format(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn formatted_files_are_left_alone() {
+        let source = std::fs::read_to_string("tests/data/config_example.yaml").unwrap();
+        let formatted = format(&source).unwrap();
+
+        assert_eq!(formatted, format(&formatted).unwrap());
+    }
+
+    #[test]
+    fn sequences_may_be_indented_or_not() {
+        let source = "deployments:\n- values:\n  - edge.yaml\n  name: edge\nversion: v2\n";
+
+        assert_eq!(
+            "version: v2\ndeployments:\n- name: edge\n  values:\n  - edge.yaml\n",
+            format(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn content_is_never_changed() {
+        assert!(format("{version: v2, chart: chart}\n").is_ok());
+        assert!(format("version: v2\nchart: [\n  a]\n").is_ok());
+        // moving the alias above its anchor would break the file
+        assert!(format("release_name: &name app\nchart: *name\n").is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use crate::manifest;
+use crate::rendered;
+use crate::{ImagesCmdOpts, OutputFormat};
+use anyhow::{bail, Context};
+use log::error;
+use regex::Regex;
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Keys of a pod spec which hold containers
+const CONTAINER_KEYS: [&str; 3] = ["containers", "initContainers", "ephemeralContainers"];
+
+/// The images sub command lists the container images of all deployments.
+pub struct ImagesCmd {
+    opts: ImagesCmdOpts,
+}
+
+/// Container image reference, split into its parts
+/// Parts which are not given in the reference are not filled with defaults, e.g. `docker.io`.
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+struct Image {
+    /// Reference as written in the manifest
+    image: String,
+
+    registry: Option<String>,
+    repository: String,
+    tag: Option<String>,
+    digest: Option<String>,
+}
+
+/// Images used by a single deployment
+#[derive(Serialize, Debug)]
+struct DeploymentImages {
+    /// Configuration file the deployment is defined in, as passed to the templexer
+    config: PathBuf,
+
+    deployment: String,
+
+    /// Sorted, without duplicates
+    images: Vec<Image>,
+}
+
+/// Image along with the deployments using it, for `--unique`
+#[derive(Serialize, Debug)]
+struct UniqueImage {
+    #[serde(flatten)]
+    image: Image,
+
+    /// Deployments as `<config>: <deployment>`
+    used_by: Vec<String>,
+}
+
+impl Image {
+    /// Split a reference like `registry.example.com:5000/team/app:1.2.3@sha256:...`
+    fn parse(reference: &str) -> Self {
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (reference, None),
+        };
+
+        // a colon after the last slash separates the tag; others belong to the registry's port
+        let (name, tag) = match name.rfind(':') {
+            Some(i) if !name[i..].contains('/') => (&name[..i], Some(name[i + 1..].to_string())),
+            _ => (name, None),
+        };
+
+        // the first component is a registry if it looks like a host name
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (Some(host.to_string()), rest.to_string())
+            }
+            _ => (None, name.to_string()),
+        };
+
+        Self {
+            image: reference.to_string(),
+            registry,
+            repository,
+            tag,
+            digest,
+        }
+    }
+
+    /// Whether the image may change without the reference changing
+    /// Images pinned by digest never float; others float if they have no tag or a floating one.
+    fn is_floating(&self, floating_tags: &Regex) -> bool {
+        if self.digest.is_some() {
+            return false;
+        }
+
+        match &self.tag {
+            Some(tag) => floating_tags.is_match(tag),
+            None => true,
+        }
+    }
+}
+
+impl ImagesCmd {
+    /// Create sub command struct to list the images of the given input file(s)
+    pub fn new(opts: ImagesCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to list the images
+    /// Fails after printing the images if any of them violates `--require-digest` or
+    /// `--forbid-floating-tags`.
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("images options: {:?}", self.opts);
+
+        let floating_tags =
+            Regex::new(&self.opts.floating_tags).context("invalid --floating-tags pattern")?;
+
+        let mut deployments = vec![];
+        for rendered in rendered::manifests(&self.opts.source)? {
+            let documents = manifest::documents(&rendered.manifest).with_context(|| {
+                format!(
+                    "can not parse manifest of deployment {} in {:?}",
+                    rendered.deployment, rendered.config
+                )
+            })?;
+
+            let mut images: Vec<Image> = documents
+                .iter()
+                .flat_map(find_images)
+                .map(|reference| Image::parse(&reference))
+                .collect();
+            images.sort();
+            images.dedup();
+
+            deployments.push(DeploymentImages {
+                config: rendered.config,
+                deployment: rendered.deployment,
+                images,
+            });
+        }
+
+        if self.opts.unique {
+            self.print_unique(&deployments)?;
+        } else {
+            self.print_grouped(&deployments)?;
+        }
+
+        let mut problems = vec![];
+        for d in &deployments {
+            for image in &d.images {
+                if self.opts.require_digest && image.digest.is_none() {
+                    problems.push(format!(
+                        "{}: {}: image {} has no digest",
+                        d.config.display(),
+                        d.deployment,
+                        image.image
+                    ));
+                }
+                if self.opts.forbid_floating_tags && image.is_floating(&floating_tags) {
+                    problems.push(format!(
+                        "{}: {}: image {} uses a floating tag",
+                        d.config.display(),
+                        d.deployment,
+                        image.image
+                    ));
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            for problem in &problems {
+                error!("{}", problem);
+            }
+            bail!("{} image(s) are not pinned", problems.len());
+        }
+
+        Ok(())
+    }
+
+    /// Print the images of each deployment
+    fn print_grouped(&self, deployments: &[DeploymentImages]) -> anyhow::Result<()> {
+        match self.opts.format {
+            OutputFormat::Text => {
+                for d in deployments {
+                    println!("{}: {}", d.config.display(), d.deployment);
+                    for image in &d.images {
+                        println!("  {}", image.image);
+                    }
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(deployments)?),
+        }
+
+        Ok(())
+    }
+
+    /// Print every image once, along with the deployments using it
+    fn print_unique(&self, deployments: &[DeploymentImages]) -> anyhow::Result<()> {
+        let mut unique: BTreeMap<&Image, Vec<String>> = BTreeMap::new();
+        for d in deployments {
+            for image in &d.images {
+                unique.entry(image).or_default().push(format!(
+                    "{}: {}",
+                    d.config.display(),
+                    d.deployment
+                ));
+            }
+        }
+
+        match self.opts.format {
+            OutputFormat::Text => {
+                for image in unique.keys() {
+                    println!("{}", image.image);
+                }
+            }
+            OutputFormat::Json => {
+                let unique: Vec<UniqueImage> = unique
+                    .into_iter()
+                    .map(|(image, used_by)| UniqueImage {
+                        image: image.clone(),
+                        used_by,
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&unique)?)
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Collect the images of all containers within the resource
+/// Containers are searched anywhere in the resource, so that all workload kinds are covered,
+/// including CronJobs and custom resources embedding a pod template.
+fn find_images(document: &Value) -> Vec<String> {
+    let mut images = vec![];
+
+    match document {
+        Value::Mapping(entries) => {
+            for (key, value) in entries {
+                let is_containers = key.as_str().is_some_and(|k| CONTAINER_KEYS.contains(&k));
+                if let (true, Value::Sequence(containers)) = (is_containers, value) {
+                    images.extend(
+                        containers
+                            .iter()
+                            .filter_map(|c| c["image"].as_str())
+                            .map(|i| i.to_string()),
+                    );
+                } else {
+                    images.extend(find_images(value));
+                }
+            }
+        }
+        Value::Sequence(items) => images.extend(items.iter().flat_map(find_images)),
+        _ => {}
+    }
+
+    images
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn references_are_parsed() {
+        let image = Image::parse("registry.example.com:5000/team/app:1.2.3@sha256:abc");
+        assert_eq!(
+            Some("registry.example.com:5000".to_string()),
+            image.registry
+        );
+        assert_eq!("team/app", image.repository);
+        assert_eq!(Some("1.2.3".to_string()), image.tag);
+        assert_eq!(Some("sha256:abc".to_string()), image.digest);
+
+        let image = Image::parse("localhost:5000/app");
+        assert_eq!(Some("localhost:5000".to_string()), image.registry);
+        assert_eq!("app", image.repository);
+        assert_eq!(None, image.tag);
+
+        let image = Image::parse("library/nginx:latest");
+        assert_eq!(None, image.registry);
+        assert_eq!("library/nginx", image.repository);
+        assert_eq!(Some("latest".to_string()), image.tag);
+    }
+
+    #[test]
+    fn floating_tags_are_detected() {
+        let floating = Regex::new("^latest$").unwrap();
+
+        assert!(Image::parse("nginx").is_floating(&floating));
+        assert!(Image::parse("nginx:latest").is_floating(&floating));
+        assert!(!Image::parse("nginx:1.25.3").is_floating(&floating));
+        assert!(!Image::parse("nginx:latest@sha256:abc").is_floating(&floating));
+    }
+
+    #[test]
+    fn images_of_all_containers_are_found() {
+        let cron_job: Value = serde_yaml::from_str(
+            r#"
+apiVersion: batch/v1
+kind: CronJob
+spec:
+  jobTemplate:
+    spec:
+      template:
+        spec:
+          initContainers:
+            - name: init
+              image: busybox:1.36
+          containers:
+            - name: job
+              image: my-job:1.0.0
+            - name: sidecar
+              image: envoy:1.28
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec!["busybox:1.36", "my-job:1.0.0", "envoy:1.28"],
+            find_images(&cron_job)
+        );
+    }
+}
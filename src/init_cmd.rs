@@ -0,0 +1,358 @@
+use crate::config::{Config, ValidationOpts};
+use crate::paths;
+use crate::process;
+use crate::InitCmdOpts;
+use anyhow::{bail, Context};
+use log::info;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Names of the values file used as base for all deployments, in order of preference
+const BASE_VALUES: [&str; 2] = ["default.yaml", "default.yml"];
+
+/// The init sub command scaffolds a configuration file for an existing chart.
+pub struct InitCmd {
+    opts: InitCmdOpts,
+}
+
+/// Configuration to be written, with all paths relative to the configuration file
+#[derive(Debug, PartialEq)]
+struct Scaffold {
+    chart: String,
+    namespace: Option<String>,
+    release_name: String,
+    output_path: String,
+
+    /// Values file applied to all deployments
+    values: Option<String>,
+
+    /// Name and values files of each deployment
+    deployments: Vec<(String, Vec<String>)>,
+}
+
+impl InitCmd {
+    /// Create sub command struct to scaffold a configuration file for the given chart
+    pub fn new(opts: InitCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to scaffold the configuration
+    /// will write the configuration file and fail if it does not pass validation
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("init options: {:?}", self.opts);
+
+        let chart = &self.opts.chart;
+        if !chart.join("Chart.yaml").is_file() {
+            bail!("{:?} is not a chart; it has no Chart.yaml", chart);
+        }
+
+        let output = match &self.opts.output {
+            Some(output) => output.clone(),
+            None => {
+                let name = chart
+                    .canonicalize()?
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "helm-templexer".to_string());
+                chart.join("..").join(format!("{}.yaml", name))
+            }
+        };
+        if output.exists() && !self.opts.force {
+            bail!("{:?} already exists; use --force to overwrite it", output);
+        }
+
+        let config_dir = match output.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let mut scaffold = Scaffold::infer(chart, &config_dir)?;
+        if let Some(namespace) = &self.opts.namespace {
+            scaffold.namespace = Some(namespace.clone());
+        }
+        if let Some(release_name) = &self.opts.release_name {
+            scaffold.release_name = release_name.clone();
+        }
+        if self.opts.interactive {
+            let stdin = std::io::stdin();
+            scaffold.ask(&mut stdin.lock(), &mut std::io::stderr())?;
+        }
+
+        // validate a copy next to the output, so that an existing file is kept if validation fails
+        let yaml = scaffold.to_yaml();
+        let mut candidate = output.as_os_str().to_owned();
+        candidate.push(".tmp");
+        let candidate = PathBuf::from(candidate);
+        std::fs::write(&candidate, &yaml)
+            .with_context(|| format!("can not write {:?}", candidate))?;
+        let validated = validate(&candidate);
+        std::fs::remove_file(&candidate)?;
+        validated.context("scaffolded configuration is invalid; nothing was written")?;
+
+        process::write_atomically(&output, yaml.as_bytes())?;
+
+        info!(
+            "wrote {:?} with {} deployment(s)",
+            output,
+            scaffold.deployments.len()
+        );
+        Ok(())
+    }
+}
+
+impl Scaffold {
+    /// Infer the configuration from the chart and its `values/` directory
+    /// `default.yaml` becomes the base values file, every other file a deployment. Files whose
+    /// name is a prefix of others, like `prod.yaml` for `prod-eu-w4.yaml`, are layered below
+    /// them instead of becoming a deployment of their own.
+    fn infer(chart: &Path, config_dir: &Path) -> anyhow::Result<Self> {
+        let name = chart_name(chart)?;
+        let path = |p: &Path| relative(p, config_dir).map(|p| p.to_string_lossy().to_string());
+
+        let mut values = None;
+        let mut stems = vec![];
+        let values_dir = chart.join("values");
+        if values_dir.is_dir() {
+            let mut files: Vec<PathBuf> = std::fs::read_dir(&values_dir)?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<_, _>>()?;
+            files.sort();
+
+            for file in files {
+                let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+                if BASE_VALUES.contains(&file_name.as_ref()) && values.is_none() {
+                    values = Some(path(&file)?);
+                } else if matches!(
+                    file.extension().and_then(|e| e.to_str()),
+                    Some("yaml" | "yml")
+                ) {
+                    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                    stems.push((stem.to_string(), path(&file)?));
+                }
+            }
+        } else {
+            info!(
+                "{:?} has no values directory; adding a single deployment",
+                chart
+            );
+        }
+
+        let is_layer = |stem: &str| {
+            let prefix = format!("{}-", stem);
+            stems.iter().any(|(other, _)| other.starts_with(&prefix))
+        };
+        let mut deployments: Vec<(String, Vec<String>)> = stems
+            .iter()
+            .filter(|(stem, _)| !is_layer(stem))
+            .map(|(stem, _)| {
+                let mut layers: Vec<&(String, String)> = stems
+                    .iter()
+                    .filter(|(other, _)| other == stem || stem.starts_with(&format!("{}-", other)))
+                    .collect();
+                // the shorter the name, the more general the values
+                layers.sort_by_key(|(other, _)| other.len());
+                (
+                    stem.clone(),
+                    layers.into_iter().map(|(_, f)| f.clone()).collect(),
+                )
+            })
+            .collect();
+        if deployments.is_empty() {
+            deployments.push(("default".to_string(), vec![]));
+        }
+
+        Ok(Self {
+            chart: path(chart)?,
+            namespace: None,
+            release_name: name,
+            output_path: "manifests".to_string(),
+            values,
+            deployments,
+        })
+    }
+
+    /// Let the user adjust the inferred configuration; empty answers keep the inferred values
+    fn ask(&mut self, input: &mut impl BufRead, output: &mut impl Write) -> anyhow::Result<()> {
+        let mut prompt = |question: &str, default: &str| -> anyhow::Result<String> {
+            write!(output, "{} [{}]: ", question, default)?;
+            output.flush()?;
+            let mut answer = String::new();
+            input.read_line(&mut answer)?;
+            let answer = answer.trim();
+            Ok(if answer.is_empty() { default } else { answer }.to_string())
+        };
+
+        self.release_name = prompt("Release name", &self.release_name)?;
+        let namespace = prompt(
+            "Namespace ('-' for none)",
+            self.namespace.as_deref().unwrap_or("-"),
+        )?;
+        self.namespace = Some(namespace).filter(|n| n != "-");
+        self.output_path = prompt("Output path", &self.output_path)?;
+
+        let mut deployments = vec![];
+        for (name, values) in std::mem::take(&mut self.deployments) {
+            let question = match values.is_empty() {
+                true => format!("Add deployment {:?}? (y/n)", name),
+                false => format!(
+                    "Add deployment {:?} using {}? (y/n)",
+                    name,
+                    values.join(", ")
+                ),
+            };
+            if !prompt(&question, "y")?.eq_ignore_ascii_case("y") {
+                continue;
+            }
+            let name = prompt("  Name", &name)?;
+            deployments.push((name, values));
+        }
+        if deployments.is_empty() {
+            bail!("at least one deployment is required");
+        }
+        self.deployments = deployments;
+
+        Ok(())
+    }
+
+    /// Format as v2 configuration file
+    fn to_yaml(&self) -> String {
+        let mut yaml = format!("---\nversion: v2\nchart: {}\n", scalar(&self.chart));
+        if let Some(namespace) = &self.namespace {
+            yaml.push_str(&format!("namespace: {}\n", scalar(namespace)));
+        }
+        yaml.push_str(&format!(
+            "release_name: {}\noutput_path: {}\n",
+            scalar(&self.release_name),
+            scalar(&self.output_path)
+        ));
+        if let Some(values) = &self.values {
+            yaml.push_str(&format!("values:\n  - {}\n", scalar(values)));
+        }
+
+        yaml.push_str("deployments:\n");
+        for (name, values) in &self.deployments {
+            yaml.push_str(&format!("  - name: {}\n", scalar(name)));
+            if !values.is_empty() {
+                yaml.push_str("    values:\n");
+                for file in values {
+                    yaml.push_str(&format!("      - {}\n", scalar(file)));
+                }
+            }
+        }
+
+        yaml
+    }
+}
+
+/// Name of the chart as given in its Chart.yaml
+fn chart_name(chart: &Path) -> anyhow::Result<String> {
+    let file = chart.join("Chart.yaml");
+    let metadata: serde_yaml::Value = serde_yaml::from_str(&std::fs::read_to_string(&file)?)
+        .with_context(|| format!("can not parse {:?}", file))?;
+
+    match metadata["name"].as_str() {
+        Some(name) => Ok(name.to_string()),
+        None => bail!("{:?} has no name", file),
+    }
+}
+
+/// Path of `path` relative to the directory `base`; both have to exist
+fn relative(path: &Path, base: &Path) -> anyhow::Result<PathBuf> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("can not resolve {:?}", path))?;
+    let base = base
+        .canonicalize()
+        .with_context(|| format!("can not resolve {:?}", base))?;
+
+    Ok(paths::relative(&path, &base))
+}
+
+/// Format a string as YAML scalar, quoting it only if needed
+fn scalar(s: &str) -> String {
+    serde_yaml::to_string(s)
+        .unwrap_or_default()
+        .trim_start_matches("---")
+        .trim()
+        .to_string()
+}
+
+/// Validate the written configuration file like the validate sub command
+fn validate(file: &Path) -> anyhow::Result<()> {
+    let cfg = Config::load(file)?;
+    cfg.switch_working_directory(file)?;
+    let result = cfg
+        .validate(&ValidationOpts {
+            skip_disabled: false,
+            config_file: Some(file.to_path_buf()),
+        })
+        .map(|_| ());
+    cfg.reset_working_directory()?;
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn deployments_are_inferred_from_values_files() {
+        let scaffold =
+            Scaffold::infer(Path::new("tests/data/nginx-chart"), Path::new("tests/data")).unwrap();
+
+        assert_eq!("nginx-chart", scaffold.chart);
+        assert_eq!("nginx-chart", scaffold.release_name);
+        assert_eq!(
+            Some("nginx-chart/values/default.yaml".to_string()),
+            scaffold.values
+        );
+        assert_eq!(
+            vec![
+                (
+                    "edge".to_string(),
+                    vec!["nginx-chart/values/edge.yaml".to_string()]
+                ),
+                (
+                    "next-edge".to_string(),
+                    vec!["nginx-chart/values/next-edge.yaml".to_string()]
+                ),
+                (
+                    "prod-eu-w4".to_string(),
+                    vec![
+                        "nginx-chart/values/prod.yaml".to_string(),
+                        "nginx-chart/values/prod-eu-w4.yaml".to_string()
+                    ]
+                ),
+                (
+                    "stage".to_string(),
+                    vec!["nginx-chart/values/stage.yaml".to_string()]
+                ),
+            ],
+            scaffold.deployments
+        );
+    }
+
+    #[test]
+    fn answers_adjust_the_scaffold() {
+        let mut scaffold = Scaffold {
+            chart: "chart".to_string(),
+            namespace: None,
+            release_name: "app".to_string(),
+            output_path: "manifests".to_string(),
+            values: None,
+            deployments: vec![
+                ("edge".to_string(), vec!["edge.yaml".to_string()]),
+                ("prod".to_string(), vec!["prod.yaml".to_string()]),
+            ],
+        };
+
+        let mut answers = "my-app\napps\n\nn\n\nprod-eu-w4\n".as_bytes();
+        scaffold.ask(&mut answers, &mut vec![]).unwrap();
+
+        assert_eq!(
+            "---\nversion: v2\nchart: chart\nnamespace: apps\nrelease_name: my-app\noutput_path: manifests\ndeployments:\n  - name: prod-eu-w4\n    values:\n      - prod.yaml\n",
+            scaffold.to_yaml()
+        );
+    }
+}
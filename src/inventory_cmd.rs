@@ -0,0 +1,134 @@
+use crate::manifest;
+use crate::rendered;
+use crate::{InventoryCmdOpts, OutputFormat};
+use anyhow::Context;
+use regex::Regex;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// The inventory sub command lists the resources each deployment consists of.
+pub struct InventoryCmd {
+    opts: InventoryCmdOpts,
+}
+
+/// A single resource of a deployment's manifest
+#[derive(Serialize, Debug, PartialEq)]
+struct Resource {
+    /// Configuration file the deployment is defined in, as passed to the templexer
+    config: PathBuf,
+
+    /// Name of the deployment
+    deployment: String,
+
+    api_version: String,
+    kind: String,
+
+    /// Namespace as set in the resource's metadata; empty if not set
+    namespace: String,
+
+    name: String,
+}
+
+impl InventoryCmd {
+    /// Create sub command struct to list the resources of the given input file(s)
+    pub fn new(opts: InventoryCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to run the inventory
+    /// will print all resources matching the filters
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("inventory options: {:?}", self.opts);
+
+        let name = match &self.opts.name {
+            Some(pattern) => Some(Regex::new(pattern).context("invalid --name pattern")?),
+            None => None,
+        };
+
+        let mut resources = vec![];
+        for rendered in rendered::manifests(&self.opts.source)? {
+            let documents = manifest::documents(&rendered.manifest).with_context(|| {
+                format!(
+                    "can not parse manifest of deployment {} in {:?}",
+                    rendered.deployment, rendered.config
+                )
+            })?;
+
+            for document in documents {
+                let field =
+                    |value: &serde_yaml::Value| value.as_str().unwrap_or_default().to_string();
+                resources.push(Resource {
+                    config: rendered.config.clone(),
+                    deployment: rendered.deployment.clone(),
+                    api_version: field(&document["apiVersion"]),
+                    kind: field(&document["kind"]),
+                    namespace: field(&document["metadata"]["namespace"]),
+                    name: field(&document["metadata"]["name"]),
+                });
+            }
+        }
+
+        resources.retain(|r| {
+            let kind_matches = self.opts.kind.is_empty()
+                || self
+                    .opts
+                    .kind
+                    .iter()
+                    .any(|k| k.eq_ignore_ascii_case(&r.kind));
+            let name_matches = name.as_ref().is_none_or(|n| n.is_match(&r.name));
+            kind_matches && name_matches
+        });
+
+        match self.opts.format {
+            OutputFormat::Text => print_table(&resources),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&resources)?),
+        }
+
+        Ok(())
+    }
+}
+
+/// Print resources as table with aligned columns; values which are not set are shown as `-`
+fn print_table(resources: &[Resource]) {
+    let mut rows = vec![[
+        "CONFIG".to_string(),
+        "DEPLOYMENT".to_string(),
+        "APIVERSION".to_string(),
+        "KIND".to_string(),
+        "NAMESPACE".to_string(),
+        "NAME".to_string(),
+    ]];
+    rows.extend(resources.iter().map(|r| {
+        let or_dash = |s: &str| {
+            if s.is_empty() {
+                "-".to_string()
+            } else {
+                s.to_string()
+            }
+        };
+        [
+            r.config.display().to_string(),
+            r.deployment.clone(),
+            or_dash(&r.api_version),
+            or_dash(&r.kind),
+            or_dash(&r.namespace),
+            or_dash(&r.name),
+        ]
+    }));
+
+    let mut widths = [0; 6];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
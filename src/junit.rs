@@ -0,0 +1,220 @@
+use crate::report::{Report, Status};
+use anyhow::Context;
+use std::fmt::Write;
+use std::path::Path;
+
+/// JUnit XML document, written via `--junit` so that CI systems can display results natively
+#[derive(Debug, Default)]
+pub struct JUnitReport {
+    pub suites: Vec<TestSuite>,
+}
+
+/// One test suite per configuration file
+#[derive(Debug)]
+pub struct TestSuite {
+    pub name: String,
+    pub cases: Vec<TestCase>,
+}
+
+/// One test case per deployment or validation check
+#[derive(Debug)]
+pub struct TestCase {
+    pub name: String,
+    pub seconds: f64,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+impl TestSuite {
+    /// Create an empty suite for the given configuration file
+    pub fn new(config_file: &Path) -> Self {
+        Self {
+            name: config_file.display().to_string(),
+            cases: vec![],
+        }
+    }
+
+    fn count(&self, f: impl Fn(&Outcome) -> bool) -> usize {
+        self.cases.iter().filter(|c| f(&c.outcome)).count()
+    }
+}
+
+impl From<&Report> for JUnitReport {
+    /// Convert a render report; deployments become test cases grouped by configuration file
+    fn from(report: &Report) -> Self {
+        let mut junit = JUnitReport::default();
+
+        for entry in &report.entries {
+            let name = entry.config.display().to_string();
+            if junit.suites.last().map(|s| &s.name) != Some(&name) {
+                junit.suites.push(TestSuite::new(&entry.config));
+            }
+
+            let outcome = match entry.status {
+                Status::Rendered => Outcome::Passed,
+                Status::Failed => Outcome::Failed(entry.error.clone().unwrap_or_default()),
+                Status::Skipped | Status::Disabled => {
+                    Outcome::Skipped(entry.skip_reason.clone().unwrap_or_default())
+                }
+            };
+
+            if let Some(suite) = junit.suites.last_mut() {
+                suite.cases.push(TestCase {
+                    name: entry
+                        .deployment
+                        .clone()
+                        .unwrap_or_else(|| "config".to_string()),
+                    seconds: entry.duration_ms as f64 / 1000.0,
+                    outcome,
+                });
+            }
+        }
+
+        junit
+    }
+}
+
+impl JUnitReport {
+    /// Serialize the report to JUnit XML
+    pub fn to_xml(&self) -> String {
+        let is_failure = |o: &Outcome| matches!(o, Outcome::Failed(_));
+        let is_skipped = |o: &Outcome| matches!(o, Outcome::Skipped(_));
+
+        let tests: usize = self.suites.iter().map(|s| s.cases.len()).sum();
+        let failures: usize = self.suites.iter().map(|s| s.count(is_failure)).sum();
+        let skipped: usize = self.suites.iter().map(|s| s.count(is_skipped)).sum();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            r#"<testsuites name="helm-templexer" tests="{}" failures="{}" skipped="{}">"#,
+            tests, failures, skipped
+        );
+
+        for suite in &self.suites {
+            let seconds: f64 = suite.cases.iter().map(|c| c.seconds).sum();
+            let _ = writeln!(
+                xml,
+                r#"  <testsuite name="{}" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+                escape(&suite.name),
+                suite.cases.len(),
+                suite.count(is_failure),
+                suite.count(is_skipped),
+                seconds
+            );
+
+            for case in &suite.cases {
+                let _ = write!(
+                    xml,
+                    r#"    <testcase name="{}" classname="{}" time="{:.3}""#,
+                    escape(&case.name),
+                    escape(&suite.name),
+                    case.seconds
+                );
+
+                match &case.outcome {
+                    Outcome::Passed => xml.push_str("/>\n"),
+                    Outcome::Failed(message) => {
+                        let summary = message.lines().next().unwrap_or_default();
+                        let _ = writeln!(
+                            xml,
+                            ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                            escape(summary),
+                            escape(message)
+                        );
+                    }
+                    Outcome::Skipped(message) => {
+                        let _ = writeln!(
+                            xml,
+                            ">\n      <skipped message=\"{}\"/>\n    </testcase>",
+                            escape(message)
+                        );
+                    }
+                }
+            }
+
+            xml.push_str("  </testsuite>\n");
+        }
+
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+
+    /// Write the report as JUnit XML to the given file
+    pub fn write(&self, file: &Path) -> anyhow::Result<()> {
+        std::fs::write(file, self.to_xml())
+            .with_context(|| format!("can not write JUnit report to {:?}", file))
+    }
+}
+
+/// Escape text for use in XML attributes and elements
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::ReportEntry;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn report_entries_are_grouped_by_config() {
+        let mut failed = ReportEntry::new(Path::new("a.yaml"), Some("prod"), Status::Rendered)
+            .fail(&anyhow::anyhow!("failed while running:\n    helm template"));
+        failed.duration_ms = 1500;
+
+        let report = Report {
+            entries: vec![
+                ReportEntry::new(Path::new("a.yaml"), Some("edge"), Status::Rendered),
+                failed,
+                ReportEntry::skipped(
+                    Path::new("b.yaml"),
+                    "edge",
+                    Status::Disabled,
+                    "config is disabled",
+                ),
+            ],
+        };
+
+        let xml = JUnitReport::from(&report).to_xml();
+
+        assert_eq!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="helm-templexer" tests="3" failures="1" skipped="1">
+  <testsuite name="a.yaml" tests="2" failures="1" skipped="0" time="1.500">
+    <testcase name="edge" classname="a.yaml" time="0.000"/>
+    <testcase name="prod" classname="a.yaml" time="1.500">
+      <failure message="failed while running:">failed while running:
+    helm template</failure>
+    </testcase>
+  </testsuite>
+  <testsuite name="b.yaml" tests="1" failures="0" skipped="1" time="0.000">
+    <testcase name="edge" classname="b.yaml" time="0.000">
+      <skipped message="config is disabled"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#,
+            xml
+        );
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;",
+            escape(r#"<a href="x">'&'</a>"#)
+        );
+    }
+}
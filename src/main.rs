@@ -1,5 +1,7 @@
 use anyhow::Context;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 use structopt::{
     clap::AppSettings::{ColoredHelp, GlobalVersion, VersionlessSubcommands},
     StructOpt,
@@ -8,11 +10,52 @@ use structopt_flags::GetWithDefault;
 
 use validate_cmd::ValidateCmd;
 
+use crate::cache_cmd::CacheCmd;
+use crate::fmt_cmd::FmtCmd;
+use crate::images_cmd::ImagesCmd;
+use crate::init_cmd::InitCmd;
+use crate::inventory_cmd::InventoryCmd;
+use crate::manifest_diff_cmd::ManifestDiffCmd;
+use crate::migrate_cmd::MigrateCmd;
+use crate::prune_cmd::PruneCmd;
 use crate::render_cmd::RenderCmd;
+use crate::uses_cmd::UsesCmd;
+use crate::values_cmd::ValuesCmd;
+use crate::values_diff_cmd::ValuesDiffCmd;
 
+mod cache;
+mod cache_cmd;
+mod changes;
 mod config;
+mod diagnostic;
+mod diff;
+#[cfg(test)]
+#[path = "../tests/integration/fixture.rs"]
+mod fixture;
+mod fmt_cmd;
+mod images_cmd;
+mod init_cmd;
+mod inventory_cmd;
+mod junit;
+mod manifest;
+mod manifest_diff_cmd;
+mod migrate_cmd;
+mod output;
+mod paths;
+mod policy;
+mod process;
+mod prune_cmd;
 mod render_cmd;
+mod rendered;
+mod report;
+mod schema;
+mod transform;
+mod uses_cmd;
 mod validate_cmd;
+mod values;
+mod values_cmd;
+mod values_diff_cmd;
+mod watch;
 
 #[derive(StructOpt, Debug)]
 #[structopt(
@@ -28,16 +71,124 @@ struct Args {
     cmd: SubCmd,
 }
 
+// parsed once per run, so the size of the render options does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(StructOpt, Debug)]
 enum SubCmd {
     #[structopt(name = "validate", about = "Validate given configuration file(s)")]
     Validate(ValidateCmdOpts),
 
+    #[structopt(
+        name = "init",
+        about = "Scaffold a configuration file for an existing chart"
+    )]
+    Init(InitCmdOpts),
+
+    #[structopt(
+        name = "fmt",
+        about = "Rewrite configuration file(s) into the canonical key order"
+    )]
+    Fmt(FmtCmdOpts),
+
+    #[structopt(
+        name = "migrate",
+        about = "Upgrade v1 configuration file(s) to v2 in place"
+    )]
+    Migrate(MigrateCmdOpts),
+
     #[structopt(
         name = "render",
         about = "Render deployments for given configuration file(s)"
     )]
     Render(RenderCmdOpts),
+
+    #[structopt(
+        name = "uses",
+        about = "List deployments which use the given values file or chart"
+    )]
+    Uses(UsesCmdOpts),
+
+    #[structopt(
+        name = "inventory",
+        about = "List the resources of each deployment, e.g. to find which deployments create a ClusterRole"
+    )]
+    Inventory(InventoryCmdOpts),
+
+    #[structopt(
+        name = "images",
+        about = "List the container images of each deployment, e.g. to mirror or scan them"
+    )]
+    Images(ImagesCmdOpts),
+
+    #[structopt(
+        name = "values",
+        about = "Print the merged values a deployment is rendered with"
+    )]
+    Values(ValuesCmdOpts),
+
+    #[structopt(
+        name = "values-diff",
+        about = "Compare the merged values of two deployments"
+    )]
+    ValuesDiff(ValuesDiffCmdOpts),
+
+    #[structopt(
+        name = "manifest-diff",
+        about = "Compare the rendered manifests of two deployments, e.g. to catch environment drift"
+    )]
+    ManifestDiff(ManifestDiffCmdOpts),
+
+    #[structopt(
+        name = "prune",
+        about = "Remove output directories of deployments which no longer exist"
+    )]
+    Prune(PruneCmdOpts),
+
+    #[structopt(name = "cache", about = "Inspect or purge the render cache")]
+    Cache(CacheCmdOpts),
+}
+
+/// Format to print results of informational sub commands in
+#[derive(Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => anyhow::bail!("unsupported format {:?}; use 'text' or 'json'", s),
+        }
+    }
+}
+
+/// Format to print validation errors in
+#[derive(Debug, PartialEq)]
+pub enum DiagnosticFormat {
+    Text,
+    Github,
+    Sarif,
+}
+
+impl FromStr for DiagnosticFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "github" => Ok(Self::Github),
+            "sarif" => Ok(Self::Sarif),
+            _ => anyhow::bail!(
+                "unsupported format {:?}; use 'text', 'github' or 'sarif'",
+                s
+            ),
+        }
+    }
 }
 
 #[derive(StructOpt, Debug)]
@@ -49,9 +200,71 @@ pub struct ValidateCmdOpts {
     #[allow(dead_code)]
     #[structopt(short, long, about = "Skip validation if `enabled` is set to false")]
     skip_disabled: bool,
+
+    /// Write a JUnit XML report to the given file; each configuration file is a test suite and each check a test case
+    #[structopt(long)]
+    junit: Option<PathBuf>,
+
+    /// Output format of validation errors: 'text', 'github' for GitHub Actions annotations or 'sarif' to print a SARIF log to stdout; all files are validated unless 'text' is used
+    #[structopt(long, default_value = "text", possible_values = &["text", "github", "sarif"])]
+    format: DiagnosticFormat,
+
+    /// Write a SARIF log of all validation errors to the given file instead of stdout
+    #[structopt(long)]
+    sarif: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct InitCmdOpts {
+    /// Chart directory to scaffold the configuration for; deployments are inferred from the files in its `values/` directory
+    chart: PathBuf,
+
+    /// Configuration file to write [default: <chart>.yaml next to the chart]
+    #[structopt(short, long)]
+    output: Option<PathBuf>,
+
+    /// Release name of all deployments [default: name of the chart]
+    #[structopt(short, long)]
+    release_name: Option<String>,
+
+    /// Namespace of all deployments
+    #[structopt(short, long)]
+    namespace: Option<String>,
+
+    /// Ask for the release name, namespace, output path and which deployments to add
+    #[structopt(short, long)]
+    interactive: bool,
+
+    /// Overwrite the configuration file if it exists
+    #[structopt(long)]
+    force: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct FmtCmdOpts {
+    /// Configuration file(s) to format (supported format: yaml)
+    input_files: Vec<PathBuf>,
+
+    /// Only check whether the file(s) are formatted; fails if any of them is not
+    #[structopt(long)]
+    check: bool,
 }
 
 #[derive(StructOpt, Debug)]
+pub struct MigrateCmdOpts {
+    /// Configuration file(s) to migrate (supported format: yaml)
+    input_files: Vec<PathBuf>,
+
+    /// Directory the templexer used to be called from; v1 paths are relative to it [default: current directory]
+    #[structopt(long)]
+    base_dir: Option<PathBuf>,
+
+    /// Print the migrated configuration file(s) to stdout instead of rewriting them
+    #[structopt(long)]
+    dry_run: bool,
+}
+
+#[derive(StructOpt, Debug, Default)]
 pub struct RenderCmdOpts {
     /// Configuration file(s) to render deployments for (supported format: yaml)
     input_files: Vec<PathBuf>,
@@ -71,13 +284,257 @@ pub struct RenderCmdOpts {
     /// Pass one or multiple command(s) to pipe the manifest for each deployment through before writing to disk, eg: 'helm-templexer render --pipe="kbld -f -" my-app.yaml'
     #[structopt(short, long, multiple = true)]
     pipe: Option<Vec<String>>,
+
+    /// Render only deployments affected by changes since the given git revision, eg: 'helm-templexer render --changed-since=origin/main my-app.yaml'
+    #[structopt(long)]
+    changed_since: Option<String>,
+
+    /// Log why each deployment was selected or skipped by `--changed-since`
+    #[structopt(long, requires = "changed-since")]
+    explain: bool,
+
+    /// Reuse manifests of deployments whose chart, value files, options and helm version did not change; stored in the given directory
+    #[structopt(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Keep running and re-render deployments whenever their configuration, chart or value files change
+    #[structopt(short, long, conflicts_with = "changed-since")]
+    watch: bool,
+
+    /// Write the manifests of all rendered deployments to stdout instead of `output_path`, separated by YAML document markers
+    #[structopt(long, conflicts_with = "watch")]
+    stdout: bool,
+
+    /// Write a JSON report with the outcome of every deployment to the given file
+    #[structopt(long)]
+    report: Option<PathBuf>,
+
+    /// Write a JUnit XML report to the given file; each configuration file is a test suite and each deployment a test case
+    #[structopt(long)]
+    junit: Option<PathBuf>,
+
+    /// Name or path of the helm binary to render with; overrides `helm.binary` of all configuration files
+    #[structopt(long)]
+    helm_binary: Option<PathBuf>,
+
+    /// Kill helm and pipe commands which take longer than the given duration, e.g. '90s' or '5m'; `timeout` of configs and deployments takes precedence
+    #[structopt(long, parse(try_from_str = process::parse_timeout))]
+    timeout: Option<Duration>,
+
+    /// Validate every rendered resource against locally cached Kubernetes JSON schemas before writing it
+    #[structopt(long)]
+    validate_manifests: bool,
+
+    /// Directory containing Kubernetes JSON schemas, e.g. a checkout of yannh/kubernetes-json-schema [default: ~/.cache/helm-templexer/schemas]
+    #[structopt(long, requires = "validate-manifests")]
+    schema_dir: Option<PathBuf>,
+
+    /// Kubernetes version to validate manifests against, e.g. '1.29'; overrides `kube_version` of all configuration files
+    #[structopt(long, requires = "validate-manifests")]
+    kube_version: Option<String>,
+
+    /// Accept resources for which no schema can be found instead of failing
+    #[structopt(long, requires = "validate-manifests")]
+    ignore_missing_schemas: bool,
+
+    /// After rendering, remove output directories of deployments which no longer exist in any of the configuration files
+    #[structopt(long, conflicts_with = "stdout")]
+    prune: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct UsesCmdOpts {
+    /// Values file or chart (directory or file within) to look up
+    path: PathBuf,
+
+    /// Configuration file(s) to search for deployments using the given path (supported format: yaml)
+    #[structopt(required = true, min_values = 1)]
+    input_files: Vec<PathBuf>,
+
+    /// Output format: 'text' or 'json'
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: OutputFormat,
+}
+
+/// Options of sub commands which inspect the manifests of deployments
+#[derive(StructOpt, Debug)]
+pub struct ManifestSourceOpts {
+    /// Configuration file(s) to inspect the deployments of (supported format: yaml)
+    input_files: Vec<PathBuf>,
+
+    /// Render the deployments instead of reading the manifests written by a previous render
+    #[structopt(long)]
+    render: bool,
+
+    /// Regular expression selecting the deployments to inspect, like 'render --filter'
+    #[structopt(short, long)]
+    filter: Option<String>,
+
+    /// Name or path of the helm binary to render with when using --render
+    #[structopt(long, requires = "render")]
+    helm_binary: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct InventoryCmdOpts {
+    #[structopt(flatten)]
+    source: ManifestSourceOpts,
+
+    /// List only resources of the given kind(s), e.g. '--kind=ClusterRole'; case-insensitive
+    #[structopt(short, long, number_of_values = 1)]
+    kind: Vec<String>,
+
+    /// List only resources whose name matches the given regular expression
+    #[structopt(short, long)]
+    name: Option<String>,
+
+    /// Output format: 'text' or 'json'
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: OutputFormat,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ImagesCmdOpts {
+    #[structopt(flatten)]
+    source: ManifestSourceOpts,
+
+    /// Print every image once instead of grouping them by deployment
+    #[structopt(long)]
+    unique: bool,
+
+    /// Fail if an image is not pinned by digest
+    #[structopt(long)]
+    require_digest: bool,
+
+    /// Fail if an image has no tag or a floating tag, unless it is pinned by digest
+    #[structopt(long)]
+    forbid_floating_tags: bool,
+
+    /// Regular expression matching the tags considered floating
+    #[structopt(long, default_value = "^(latest|stable|edge|main|master|dev|nightly)$")]
+    floating_tags: String,
+
+    /// Output format: 'text' or 'json'
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: OutputFormat,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ValuesCmdOpts {
+    /// Configuration file the deployment is defined in (supported format: yaml)
+    input_file: PathBuf,
+
+    /// Name of the deployment to print the values of
+    #[structopt(short, long)]
+    deployment: String,
+
+    /// Additional options as passed to 'render', e.g. '--set-string image.tag=${revision}'
+    #[structopt(short, long, multiple = true)]
+    additional_options: Option<Vec<String>>,
+
+    /// Annotate each value with the values file or `--set` option that last set it
+    #[structopt(long)]
+    show_sources: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ValuesDiffCmdOpts {
+    /// Configuration file the deployments are defined in (supported format: yaml)
+    input_file: PathBuf,
+
+    /// Name of the deployment to compare from, e.g. 'stage-eu-w4'
+    from: String,
+
+    /// Name of the deployment to compare to, e.g. 'prod-eu-w4'
+    to: String,
+
+    /// Configuration file the second deployment is defined in, if it differs from the first one
+    #[structopt(long)]
+    to_config: Option<PathBuf>,
+
+    /// Additional options as passed to 'render'; apply to both deployments
+    #[structopt(short, long, multiple = true)]
+    additional_options: Option<Vec<String>>,
+
+    /// Output format: 'text' or 'json'
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: OutputFormat,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ManifestDiffCmdOpts {
+    /// Configuration file the deployments are defined in (supported format: yaml)
+    input_file: PathBuf,
+
+    /// Name of the deployment to compare from, e.g. 'stage-eu-w4'
+    from: String,
+
+    /// Name of the deployment to compare to, e.g. 'prod-eu-w4'
+    to: String,
+
+    /// Configuration file the second deployment is defined in, if it differs from the first one
+    #[structopt(long)]
+    to_config: Option<PathBuf>,
+
+    /// Render the deployments instead of reading the manifests written by a previous render
+    #[structopt(long)]
+    render: bool,
+
+    /// Name or path of the helm binary to render with when using --render
+    #[structopt(long, requires = "render")]
+    helm_binary: Option<PathBuf>,
+
+    /// Leave out expected differences, given as '[Kind:]path', e.g. 'Deployment:spec.replicas' or 'spec.rules[*].host'
+    #[structopt(short, long, number_of_values = 1)]
+    ignore: Vec<String>,
+
+    /// Fail if the manifests differ
+    #[structopt(long)]
+    exit_code: bool,
+
+    /// Output format: 'text' or 'json'
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: OutputFormat,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct PruneCmdOpts {
+    /// Configuration file(s) whose deployments are kept; configs sharing an `output_path` must be passed together (supported format: yaml)
+    input_files: Vec<PathBuf>,
+
+    /// Only list the orphaned output directories instead of removing them
+    #[structopt(long)]
+    dry_run: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct CacheCmdOpts {
+    #[structopt(subcommand)]
+    action: CacheAction,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum CacheAction {
+    #[structopt(name = "stats", about = "Show number and size of cached manifests")]
+    Stats {
+        /// Directory the render cache is stored in
+        cache_dir: PathBuf,
+    },
+
+    #[structopt(name = "purge", about = "Remove all cached manifests")]
+    Purge {
+        /// Directory the render cache is stored in
+        cache_dir: PathBuf,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::from_args();
 
     let log_level = args.verbose.get_with_default(log::LevelFilter::Info);
+    // logs always go to stderr, so that `render --stdout` can be piped into other tools
     env_logger::Builder::from_default_env()
+        .target(env_logger::Target::Stderr)
         .filter_level(log_level)
         .init();
 
@@ -85,7 +542,28 @@ fn main() -> anyhow::Result<()> {
         SubCmd::Validate(opts) => ValidateCmd::new(opts)
             .run()
             .context("Configuration failed validation")?,
+        SubCmd::Init(opts) => InitCmd::new(opts).run().context("Scaffolding failed")?,
+        SubCmd::Fmt(opts) => FmtCmd::new(opts).run().context("Formatting failed")?,
+        SubCmd::Migrate(opts) => MigrateCmd::new(opts).run().context("Migration failed")?,
         SubCmd::Render(opts) => RenderCmd::new(opts).run().context("Rendering failed")?,
+        SubCmd::Uses(opts) => UsesCmd::new(opts).run().context("Lookup failed")?,
+        SubCmd::Inventory(opts) => InventoryCmd::new(opts).run().context("Inventory failed")?,
+        SubCmd::Images(opts) => ImagesCmd::new(opts)
+            .run()
+            .context("Listing images failed")?,
+        SubCmd::Values(opts) => ValuesCmd::new(opts)
+            .run()
+            .context("Merging values failed")?,
+        SubCmd::ValuesDiff(opts) => ValuesDiffCmd::new(opts)
+            .run()
+            .context("Comparing values failed")?,
+        SubCmd::ManifestDiff(opts) => ManifestDiffCmd::new(opts)
+            .run()
+            .context("Comparing manifests failed")?,
+        SubCmd::Prune(opts) => PruneCmd::new(opts).run().context("Pruning failed")?,
+        SubCmd::Cache(opts) => CacheCmd::new(opts)
+            .run()
+            .context("Cache operation failed")?,
     };
 
     Ok(())
@@ -0,0 +1,54 @@
+use anyhow::Context;
+use serde::Deserialize;
+use serde_yaml::Value;
+use sha2::{Digest, Sha256};
+
+/// Split a rendered multi-document manifest into its resources
+/// Documents which are empty or only contain comments are dropped.
+pub fn documents(manifest: &str) -> anyhow::Result<Vec<Value>> {
+    let mut documents = vec![];
+
+    for document in serde_yaml::Deserializer::from_str(manifest) {
+        let value = Value::deserialize(document).context("can not parse rendered manifest")?;
+        if !value.is_null() {
+            documents.push(value);
+        }
+    }
+
+    Ok(documents)
+}
+
+/// Hex-encoded sha256 checksum of the given manifest
+pub fn sha256(manifest: &[u8]) -> String {
+    Sha256::digest(manifest)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn empty_documents_are_dropped() {
+        let manifest = "---\n# Source: chart/templates/empty.yaml\n---\nkind: Service\n---\n---\nkind: Deployment\n";
+
+        let documents = documents(manifest).unwrap();
+        let kinds: Vec<&str> = documents
+            .iter()
+            .map(|d| d["kind"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(vec!["Service", "Deployment"], kinds);
+    }
+
+    #[test]
+    fn checksum_is_hex_encoded_sha256() {
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            sha256(b"")
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use crate::config::Config;
+use crate::diff::{self, Change, Difference};
+use crate::manifest;
+use crate::rendered;
+use crate::{ManifestDiffCmdOpts, ManifestSourceOpts, OutputFormat};
+use anyhow::{anyhow, bail, Context};
+use log::info;
+use regex::Regex;
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Placeholder replacing the release name in resource names, if the deployments' release names differ
+const RELEASE_PLACEHOLDER: &str = "<release>";
+
+/// The manifest-diff sub command compares the rendered manifests of two deployments.
+pub struct ManifestDiffCmd {
+    opts: ManifestDiffCmdOpts,
+}
+
+/// Single resource which differs between the two deployments
+#[derive(Serialize, Debug, PartialEq)]
+struct ResourceDifference {
+    /// Identity of the resource, e.g. `Deployment/my-namespace/my-app`
+    resource: String,
+
+    change: Change,
+
+    /// Differing fields of a changed resource; empty if it was added or removed
+    differences: Vec<Difference>,
+}
+
+/// Field to leave out of the comparison, given as `[Kind:]path`, e.g. `Deployment:spec.replicas`
+/// `*` matches a single key or list index; a path also covers all fields below it.
+#[derive(Debug)]
+struct Ignore {
+    kind: Option<String>,
+    path: Regex,
+}
+
+impl Ignore {
+    fn parse(ignore: &str) -> anyhow::Result<Self> {
+        let (kind, path) = match ignore.split_once(':') {
+            Some((kind, path)) => (Some(kind.to_string()), path),
+            None => (None, ignore),
+        };
+
+        let pattern = regex::escape(path)
+            .replace(r"\[\*\]", r"\[\d+\]")
+            .replace(r"\*", r"[^.\[]+");
+        let path = Regex::new(&format!(r"^{}($|[.\[])", pattern))
+            .with_context(|| format!("invalid --ignore {:?}", ignore))?;
+
+        Ok(Self { kind, path })
+    }
+
+    fn matches(&self, kind: &str, path: &str) -> bool {
+        self.kind
+            .as_ref()
+            .is_none_or(|k| k.eq_ignore_ascii_case(kind))
+            && self.path.is_match(path)
+    }
+}
+
+impl ManifestDiffCmd {
+    /// Create sub command struct to compare the manifests of the given deployments
+    pub fn new(opts: ManifestDiffCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to compare the manifests
+    /// will print every resource which was added, removed or changed from the first to the second deployment
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("manifest-diff options: {:?}", self.opts);
+
+        let ignores = self
+            .opts
+            .ignore
+            .iter()
+            .map(|i| Ignore::parse(i))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let to_config = self
+            .opts
+            .to_config
+            .as_ref()
+            .unwrap_or(&self.opts.input_file);
+        let (from, from_release) = self.load(&self.opts.input_file, &self.opts.from)?;
+        let (to, to_release) = self.load(to_config, &self.opts.to)?;
+
+        // resources are usually prefixed with the release name, which must not break matching them
+        let placeholder = (from_release != to_release).then_some((from_release, to_release));
+        let from = resources(&from, placeholder.as_ref().map(|p| p.0.as_str()))?;
+        let to = resources(&to, placeholder.as_ref().map(|p| p.1.as_str()))?;
+
+        let differences = diff(from, to, &ignores);
+        if differences.is_empty() {
+            info!(
+                "manifests of {} and {} are identical",
+                self.opts.from, self.opts.to
+            );
+        }
+
+        match self.opts.format {
+            OutputFormat::Text => {
+                for r in &differences {
+                    let marker = match r.change {
+                        Change::Added => "+",
+                        Change::Removed => "-",
+                        Change::Changed => "~",
+                    };
+                    println!("{} {}", marker, r.resource);
+                    for d in &r.differences {
+                        println!("    {}", d);
+                    }
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&differences)?),
+        }
+
+        if self.opts.exit_code && !differences.is_empty() {
+            bail!("{} resource(s) differ", differences.len());
+        }
+
+        Ok(())
+    }
+
+    /// Read or render the manifest of the given deployment, along with its release name
+    fn load(&self, file: &Path, deployment: &str) -> anyhow::Result<(String, String)> {
+        let cfg = Config::load(file)?;
+        let d = cfg
+            .deployments
+            .iter()
+            .find(|d| d.name == deployment)
+            .ok_or_else(|| anyhow!("{}: no deployment named {:?}", file.display(), deployment))?;
+
+        let source = ManifestSourceOpts {
+            input_files: vec![file.to_path_buf()],
+            render: self.opts.render,
+            filter: Some(format!("^{}$", regex::escape(deployment))),
+            helm_binary: self.opts.helm_binary.clone(),
+        };
+        let rendered = rendered::manifests(&source)?.pop().ok_or_else(|| {
+            anyhow!(
+                "{}: no manifest for deployment {}; is it enabled?",
+                file.display(),
+                deployment
+            )
+        })?;
+
+        Ok((rendered.manifest, cfg.release_name(d).to_string()))
+    }
+}
+
+/// Index the resources of a manifest by their identity
+/// If given, the release name is replaced by a placeholder in the names of resources.
+fn resources(manifest: &str, release: Option<&str>) -> anyhow::Result<BTreeMap<String, Value>> {
+    let mut resources = BTreeMap::new();
+
+    for mut document in manifest::documents(manifest)? {
+        let field = |value: &Value| value.as_str().unwrap_or_default().to_string();
+        let mut name = field(&document["metadata"]["name"]);
+        if let Some(release) = release.filter(|r| !r.is_empty()) {
+            // replaced in the resource as well, so that the name is not reported as changed
+            name = name.replace(release, RELEASE_PLACEHOLDER);
+            if let Some(metadata) = document["metadata"].as_mapping_mut() {
+                metadata.insert(Value::from("name"), Value::from(name.as_str()));
+            }
+        }
+
+        let identity = match field(&document["metadata"]["namespace"]) {
+            namespace if namespace.is_empty() => {
+                format!("{}/{}", field(&document["kind"]), name)
+            }
+            namespace => format!("{}/{}/{}", field(&document["kind"]), namespace, name),
+        };
+        resources.insert(identity, document);
+    }
+
+    Ok(resources)
+}
+
+/// Compare resources by identity, leaving out ignored fields
+fn diff(
+    from: BTreeMap<String, Value>,
+    mut to: BTreeMap<String, Value>,
+    ignores: &[Ignore],
+) -> Vec<ResourceDifference> {
+    let mut differences = vec![];
+
+    for (resource, document) in from {
+        let other = match to.remove(&resource) {
+            Some(other) => other,
+            None => {
+                differences.push(ResourceDifference {
+                    resource,
+                    change: Change::Removed,
+                    differences: vec![],
+                });
+                continue;
+            }
+        };
+
+        let kind = document["kind"].as_str().unwrap_or_default();
+        let mut fields = diff::compare(flatten(&document), flatten(&other));
+        fields.retain(|d| !ignores.iter().any(|i| i.matches(kind, &d.path)));
+
+        if !fields.is_empty() {
+            differences.push(ResourceDifference {
+                resource,
+                change: Change::Changed,
+                differences: fields,
+            });
+        }
+    }
+    for resource in to.into_keys() {
+        differences.push(ResourceDifference {
+            resource,
+            change: Change::Added,
+            differences: vec![],
+        });
+    }
+
+    differences.sort_by(|a, b| a.resource.cmp(&b.resource));
+    differences
+}
+
+/// Flatten a resource into paths like `spec.template.spec.containers[0].image`
+/// Unlike values, lists are descended into, so that a single changed field is reported as such.
+fn flatten(document: &Value) -> BTreeMap<String, Value> {
+    fn walk(value: &Value, path: String, flat: &mut BTreeMap<String, Value>) {
+        match value {
+            Value::Mapping(entries) if !entries.is_empty() => {
+                for (key, item) in entries {
+                    let key = key
+                        .as_str()
+                        .map(String::from)
+                        .unwrap_or_else(|| serde_json::to_string(key).unwrap_or_default());
+                    let child = if path.is_empty() {
+                        key
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    walk(item, child, flat);
+                }
+            }
+            Value::Sequence(items) if !items.is_empty() => {
+                for (i, item) in items.iter().enumerate() {
+                    walk(item, format!("{}[{}]", path, i), flat);
+                }
+            }
+            _ => {
+                flat.insert(path, value.clone());
+            }
+        }
+    }
+
+    let mut flat = BTreeMap::new();
+    walk(document, String::new(), &mut flat);
+    flat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const FROM: &str = r#"
+apiVersion: v1
+kind: Service
+metadata:
+  name: stage-app
+spec:
+  ports:
+    - port: 80
+---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: stage-app
+  namespace: apps
+spec:
+  replicas: 1
+  template:
+    spec:
+      containers:
+        - name: app
+          image: app:1.0.0
+---
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: stage-app-debug
+"#;
+
+    const TO: &str = r#"
+apiVersion: v1
+kind: Service
+metadata:
+  name: prod-app
+spec:
+  ports:
+    - port: 80
+---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: prod-app
+  namespace: apps
+spec:
+  replicas: 3
+  template:
+    spec:
+      containers:
+        - name: app
+          image: app:1.1.0
+"#;
+
+    #[test]
+    fn resources_are_matched_by_identity() {
+        let from = resources(FROM, Some("stage")).unwrap();
+        let to = resources(TO, Some("prod")).unwrap();
+
+        let differences = diff(from, to, &[]);
+
+        assert_eq!(2, differences.len());
+        assert_eq!("ConfigMap/<release>-app-debug", differences[0].resource);
+        assert_eq!(Change::Removed, differences[0].change);
+        assert_eq!("Deployment/apps/<release>-app", differences[1].resource);
+        assert_eq!(Change::Changed, differences[1].change);
+
+        let fields: Vec<String> = differences[1]
+            .differences
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "~ spec.replicas: 1 -> 3",
+                r#"~ spec.template.spec.containers[0].image: "app:1.0.0" -> "app:1.1.0""#,
+            ],
+            fields
+        );
+    }
+
+    #[test]
+    fn ignored_fields_are_left_out() {
+        let from = resources(FROM, Some("stage")).unwrap();
+        let to = resources(TO, Some("prod")).unwrap();
+        let ignores = vec![
+            Ignore::parse("deployment:spec.replicas").unwrap(),
+            Ignore::parse("spec.template.spec.containers[*].image").unwrap(),
+        ];
+
+        let differences = diff(from, to, &ignores);
+
+        assert_eq!(1, differences.len());
+        assert_eq!(Change::Removed, differences[0].change);
+    }
+
+    #[test]
+    fn ignores_match_whole_keys_and_fields_below() {
+        let ignore = Ignore::parse("metadata.labels").unwrap();
+        assert!(ignore.matches("Service", "metadata.labels"));
+        assert!(ignore.matches("Service", "metadata.labels.app"));
+        assert!(!ignore.matches("Service", "metadata.labelsExtra"));
+
+        let ignore = Ignore::parse("Ingress:spec.rules[*].host").unwrap();
+        assert!(ignore.matches("Ingress", "spec.rules[1].host"));
+        assert!(!ignore.matches("Service", "spec.rules[1].host"));
+
+        let ignore = Ignore::parse("metadata.annotations.*").unwrap();
+        assert!(ignore.matches("Service", "metadata.annotations.checksum/config"));
+    }
+}
@@ -0,0 +1,323 @@
+use crate::config::Config;
+use crate::diagnostic::{self, Scalar};
+use crate::paths;
+use crate::process;
+use crate::MigrateCmdOpts;
+use anyhow::{bail, Context};
+use log::{info, warn};
+use regex::Regex;
+use std::path::Path;
+use yaml_rust::scanner::TScalarStyle;
+
+/// Fields holding paths, which were relative to the directory the templexer was called from in v1
+const PATH_FIELDS: &str = r"^(chart|output_path|values\[\d+\]|deployments\[\d+\]\.values\[\d+\])$";
+
+/// Fields holding options passed to `helm template`
+const OPTION_FIELDS: &str = r"^(deployments\[\d+\]\.)?additional_options\[\d+\]$";
+
+/// Options of `helm template` taking a path, which can not be adjusted automatically
+const PATH_OPTIONS: [&str; 9] = [
+    "-f",
+    "--values",
+    "--set-file",
+    "--post-renderer",
+    "--repository-config",
+    "--registry-config",
+    "--ca-file",
+    "--cert-file",
+    "--key-file",
+];
+
+/// The migrate sub command upgrades v1 configuration files to v2.
+pub struct MigrateCmd {
+    opts: MigrateCmdOpts,
+}
+
+/// Result of migrating a single file
+#[derive(Debug, Default, PartialEq)]
+struct Migration {
+    /// Content of the migrated file
+    content: String,
+
+    /// Line and description of everything which needs to be adjusted manually
+    issues: Vec<(usize, String)>,
+}
+
+impl MigrateCmd {
+    /// Create sub command struct to migrate the given input file(s)
+    pub fn new(opts: MigrateCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to run the migration
+    /// will rewrite all v1 files in place and report what could not be converted
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("migrate options: {:?}", self.opts);
+
+        let cwd = std::env::current_dir()?;
+        let base_dir = match &self.opts.base_dir {
+            Some(dir) => cwd.join(dir),
+            None => cwd.clone(),
+        };
+
+        let mut issues = 0;
+        for file in &self.opts.input_files {
+            let source = std::fs::read_to_string(file)
+                .with_context(|| format!("can not read {:?}", file))?;
+            let config_dir = cwd
+                .join(file)
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+
+            let migration = match migrate(&source, &base_dir, &config_dir)
+                .with_context(|| format!("can not migrate {:?}", file))?
+            {
+                Some(migration) => migration,
+                None => {
+                    info!("{}: already v2; nothing to migrate", file.display());
+                    continue;
+                }
+            };
+
+            for (line, issue) in &migration.issues {
+                warn!("{}:{}: {}", file.display(), line, issue);
+            }
+            issues += migration.issues.len();
+
+            if self.opts.dry_run {
+                println!("# {}\n{}", file.display(), migration.content);
+            } else {
+                process::write_atomically(file, migration.content.as_bytes())?;
+                info!("{}: migrated to v2", file.display());
+            }
+        }
+
+        if issues > 0 {
+            warn!("{} issue(s) need to be resolved manually", issues);
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert a v1 configuration to v2, keeping its formatting and comments
+/// Relative paths are resolved against `base_dir` and made relative to `config_dir`. Returns
+/// nothing if the configuration is v2 already.
+fn migrate(source: &str, base_dir: &Path, config_dir: &Path) -> anyhow::Result<Option<Migration>> {
+    let scalars = diagnostic::scalars(source)?;
+    match scalars.iter().find(|s| s.field == "version") {
+        Some(s) if s.value == "v1" => {}
+        Some(s) if s.value == "v2" => return Ok(None),
+        Some(s) => bail!("unsupported version {:?}; only v1 can be migrated", s.value),
+        None => bail!("missing field `version`"),
+    }
+
+    let path_fields = Regex::new(PATH_FIELDS)?;
+    let option_fields = Regex::new(OPTION_FIELDS)?;
+    let mut migration = Migration::default();
+    let mut edits = vec![];
+
+    for scalar in &scalars {
+        if scalar.field == "version" {
+            edits.push((scalar, "v2".to_string()));
+        } else if path_fields.is_match(&scalar.field) && !Path::new(&scalar.value).is_absolute() {
+            let path = paths::relative(&base_dir.join(&scalar.value), config_dir);
+            let path = path.to_string_lossy().to_string();
+            if path != scalar.value {
+                edits.push((scalar, path));
+            }
+        } else if option_fields.is_match(&scalar.field) {
+            migration.issues.extend(check_option(scalar));
+        }
+    }
+
+    let mut content: Vec<char> = source.chars().collect();
+    edits.sort_by_key(|(scalar, _)| std::cmp::Reverse(scalar.index));
+    for (scalar, value) in edits {
+        match raw_len(&content, scalar) {
+            Some(len) => {
+                content.splice(
+                    scalar.index..scalar.index + len,
+                    quote(&value, scalar.style).chars(),
+                );
+            }
+            None => migration.issues.push((
+                scalar.line,
+                format!(
+                    "{} can not be rewritten in place; change it to {:?}",
+                    scalar.field, value
+                ),
+            )),
+        }
+    }
+    migration.content = content.into_iter().collect();
+
+    // the output layout changed, which affects every tool reading the manifests
+    let output_line = scalars
+        .iter()
+        .find(|s| s.field == "output_path")
+        .map_or(1, |s| s.line);
+    migration.issues.push((
+        output_line,
+        "manifests are now written to <output_path>/<deployment>/<release>/manifest.yaml; remove the former output and update tools reading it".to_string(),
+    ));
+    migration.issues.sort();
+
+    // never replace a v1 file with one which can not be loaded either
+    Config::parse(&migration.content).context("migrated configuration can not be loaded")?;
+
+    Ok(Some(migration))
+}
+
+/// Report options of `helm template` which refer to paths or are handled by the templexer
+fn check_option(scalar: &Scalar) -> Vec<(usize, String)> {
+    let mut issues = vec![];
+
+    for token in scalar.value.split_whitespace() {
+        let flag = token.split('=').next().unwrap_or_default();
+        if flag == "--output-dir" {
+            issues.push((
+                scalar.line,
+                format!(
+                    "{}: remove --output-dir; manifests are written to output_path",
+                    scalar.field
+                ),
+            ));
+        } else if PATH_OPTIONS.contains(&flag) {
+            issues.push((
+                scalar.line,
+                format!(
+                    "{}: the path passed to {} is now relative to the configuration file; adjust it manually",
+                    scalar.field, flag
+                ),
+            ));
+        }
+    }
+
+    issues
+}
+
+/// Length of the scalar as written in the source, including quotes
+/// Not known for block scalars and plain scalars spanning multiple lines.
+fn raw_len(source: &[char], scalar: &Scalar) -> Option<usize> {
+    let rest = source.get(scalar.index..)?;
+    let expected: Vec<char> = match scalar.style {
+        TScalarStyle::Plain => scalar.value.chars().collect(),
+        TScalarStyle::SingleQuoted => format!("'{}'", scalar.value.replace('\'', "''"))
+            .chars()
+            .collect(),
+        TScalarStyle::DoubleQuoted => {
+            let mut i = 1;
+            while *rest.get(i)? != '"' {
+                i += if rest[i] == '\\' { 2 } else { 1 };
+            }
+            return Some(i + 1);
+        }
+        _ => return None,
+    };
+
+    rest.starts_with(&expected).then_some(expected.len())
+}
+
+/// Format the value in the style of the original scalar; plain values are quoted if necessary
+fn quote(value: &str, style: TScalarStyle) -> String {
+    let is_plain = Regex::new(r"^[\w./~][\w./~-]*$").is_ok_and(|r| r.is_match(value));
+
+    match style {
+        TScalarStyle::Plain if is_plain => value.to_string(),
+        TScalarStyle::SingleQuoted => format!("'{}'", value.replace('\'', "''")),
+        _ => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn migrated(source: &str, base_dir: &str, config_dir: &str) -> Migration {
+        migrate(source, Path::new(base_dir), Path::new(config_dir))
+            .unwrap()
+            .unwrap()
+    }
+
+    const V1: &str = r#"---
+# deployed by the platform team
+version: v1
+chart: charts/app # relative to the repository root
+release_name: app
+output_path: "manifests/app"
+additional_options:
+  - "--output-dir /tmp/out"
+values:
+  - 'configs/app/values/base.yaml'
+deployments:
+  - name: edge
+    values:
+      - configs/app/values/edge.yaml
+    additional_options:
+      - "-f configs/app/values/extra.yaml"
+"#;
+
+    #[test]
+    fn paths_are_made_relative_to_the_config_file() {
+        let migration = migrated(V1, "/repo", "/repo/configs/app");
+
+        assert_eq!(
+            r#"---
+# deployed by the platform team
+version: v2
+chart: ../../charts/app # relative to the repository root
+release_name: app
+output_path: "../../manifests/app"
+additional_options:
+  - "--output-dir /tmp/out"
+values:
+  - 'values/base.yaml'
+deployments:
+  - name: edge
+    values:
+      - values/edge.yaml
+    additional_options:
+      - "-f configs/app/values/extra.yaml"
+"#,
+            migration.content
+        );
+    }
+
+    #[test]
+    fn options_which_can_not_be_converted_are_reported() {
+        let migration = migrated(V1, "/repo", "/repo/configs/app");
+        let lines: Vec<usize> = migration.issues.iter().map(|(line, _)| *line).collect();
+
+        assert_eq!(vec![6, 8, 16], lines);
+        assert!(migration.issues[1].1.contains("remove --output-dir"));
+        assert!(migration.issues[2]
+            .1
+            .starts_with("deployments[0].additional_options[0]: the path passed to -f"));
+    }
+
+    #[test]
+    fn v2_configs_are_left_alone() {
+        let v2 = V1.replace("version: v1", "version: v2");
+        assert_eq!(
+            None,
+            migrate(&v2, Path::new("/repo"), Path::new("/repo/configs/app")).unwrap()
+        );
+        assert!(migrate("version: v3", Path::new("/"), Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn unloadable_results_are_rejected() {
+        let incomplete = V1.split("deployments:").next().unwrap();
+        let err = migrate(
+            incomplete,
+            Path::new("/repo"),
+            Path::new("/repo/configs/app"),
+        )
+        .unwrap_err();
+
+        assert!(format!("{:#}", err).starts_with("migrated configuration can not be loaded"));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::config::Config;
+use anyhow::{bail, Context};
+use log::{debug, info};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// File marking an output directory as written by the templexer
+/// Only directories carrying it are ever pruned.
+pub const OWNERSHIP_MARKER: &str = ".helm-templexer";
+
+const MARKER_CONTENT: &str = "# This directory is managed by helm-templexer; it is replaced on every render\n# and removed by `helm-templexer prune` once its deployment no longer exists.\n";
+
+/// Mark the given output directory as written by the templexer
+pub fn mark_owned(dir: &Path) -> anyhow::Result<()> {
+    std::fs::write(dir.join(OWNERSHIP_MARKER), MARKER_CONTENT)
+        .with_context(|| format!("can not write ownership marker to {:?}", dir))
+}
+
+/// Whether the given directory was written by the templexer
+pub fn is_owned(dir: &Path) -> bool {
+    dir.join(OWNERSHIP_MARKER).is_file()
+}
+
+/// Make sure the given output directory may be removed before replacing it
+/// It must be within the output root, which must not be a protected directory, and carry an
+/// ownership marker. Directories which only contain a manifest were written before the marker
+/// existed and are accepted as well.
+pub fn check_removable(root: &Path, dir: &Path) -> anyhow::Result<()> {
+    let refuse = |reason: &str| bail!("refusing to remove {:?}: {}", dir, reason);
+
+    let (root, absolute) = match (root.canonicalize(), dir.canonicalize()) {
+        (Ok(root), Ok(dir)) => (root, dir),
+        _ => return refuse("it is not within the output path"),
+    };
+    if absolute == root || !absolute.starts_with(&root) {
+        return refuse(&format!("it is not within the output path {:?}", root));
+    }
+    check_root(&root)?;
+
+    if !is_owned(dir) && !only_contains_manifest(dir)? {
+        return refuse(&format!(
+            "it was not written by helm-templexer; remove it manually or add a {} file to it",
+            OWNERSHIP_MARKER
+        ));
+    }
+
+    Ok(())
+}
+
+/// Make sure the given absolute output root is not a directory nothing must be removed from
+/// Refuses the root of the filesystem, the home directory and the root of the git repository,
+/// as well as all directories containing them.
+fn check_root(root: &Path) -> anyhow::Result<()> {
+    let mut protected = vec![];
+    if let Some(home) = std::env::var_os("HOME").and_then(|h| PathBuf::from(h).canonicalize().ok())
+    {
+        protected.push(home);
+    }
+    for dir in [std::env::current_dir()?, root.to_path_buf()] {
+        if let Some(repo) = dir.ancestors().find(|d| d.join(".git").exists()) {
+            protected.push(repo.to_path_buf());
+        }
+    }
+
+    if root.parent().is_none() || protected.iter().any(|p| p.starts_with(root)) {
+        bail!(
+            "refusing to remove anything from output path {:?}; point `output_path` to a dedicated directory",
+            root
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether the directory contains nothing but a manifest
+fn only_contains_manifest(dir: &Path) -> anyhow::Result<bool> {
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        if name != "manifest.yaml" && name != "manifest.yaml.tmp" {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Find output directories which are not produced by any deployment of the given config files
+/// Output directories are `<output_path>/<deployment>/<release>`; configs sharing an
+/// `output_path` are considered together. Directories of disabled deployments are kept, as are
+/// directories without an ownership marker.
+pub fn find_orphans(input_files: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut expected: BTreeMap<PathBuf, BTreeSet<PathBuf>> = BTreeMap::new();
+
+    for file in input_files {
+        let cfg = Config::load(file)?;
+        cfg.switch_working_directory(file)?;
+        let found = output_dirs(&cfg);
+        cfg.reset_working_directory()?;
+
+        let (root, dirs) = found?;
+        if let Some(root) = root {
+            expected.entry(root).or_default().extend(dirs);
+        }
+    }
+
+    let mut orphans = vec![];
+    for (root, dirs) in &expected {
+        check_root(root)?;
+        orphans.extend(orphans_in(root, dirs)?);
+    }
+
+    Ok(orphans)
+}
+
+/// Owned output directories within the given root which are not expected
+fn orphans_in(root: &Path, expected: &BTreeSet<PathBuf>) -> anyhow::Result<Vec<PathBuf>> {
+    let mut orphans = vec![];
+
+    for deployment in subdirectories(root)? {
+        for release in subdirectories(&deployment)? {
+            if expected.contains(&release) {
+                continue;
+            }
+            if !is_owned(&release) {
+                debug!("keeping {:?}; it has no ownership marker", release);
+                continue;
+            }
+            orphans.push(release);
+        }
+    }
+
+    Ok(orphans)
+}
+
+/// Remove the given orphaned output directories, along with deployment directories left empty
+pub fn prune(orphans: &[PathBuf]) -> anyhow::Result<()> {
+    for orphan in orphans {
+        info!("removing orphaned output directory {:?}", orphan);
+        std::fs::remove_dir_all(orphan).with_context(|| format!("can not remove {:?}", orphan))?;
+
+        if let Some(parent) = orphan.parent() {
+            if std::fs::read_dir(parent)?.next().is_none() {
+                std::fs::remove_dir(parent)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Absolute output root of the config along with the output directories of its deployments
+/// The root is not set if it does not exist, i.e. nothing was rendered yet.
+/// Make sure to switch the working directory to the config file before calling this.
+fn output_dirs(cfg: &Config) -> anyhow::Result<(Option<PathBuf>, Vec<PathBuf>)> {
+    if !cfg.output_path.is_dir() {
+        return Ok((None, vec![]));
+    }
+
+    let root = cfg.output_path.canonicalize()?;
+    let dirs = cfg
+        .deployments
+        .iter()
+        .filter_map(|d| {
+            let manifest = cfg.manifest_path(d);
+            let dir = manifest.parent()?.strip_prefix(&cfg.output_path).ok()?;
+            Some(root.join(dir))
+        })
+        .collect();
+
+    Ok((Some(root), dirs))
+}
+
+/// Directories directly within the given one, sorted by name
+fn subdirectories(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    for entry in std::fs::read_dir(dir).with_context(|| format!("can not read {:?}", dir))? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempDir;
+
+    #[test]
+    fn only_owned_directories_of_removed_deployments_are_orphans() -> anyhow::Result<()> {
+        let temp = TempDir::new("prune-orphans")?;
+        let dir = temp.path();
+        for (deployment, owned) in [
+            ("edge/my-app", true),
+            ("renamed/my-app", true),
+            ("hand-written/my-app", false),
+        ] {
+            let output = dir.join("manifests").join(deployment);
+            std::fs::create_dir_all(&output)?;
+            if owned {
+                mark_owned(&output)?;
+            }
+        }
+        let root = dir.join("manifests");
+        let expected = BTreeSet::from([root.join("edge/my-app")]);
+
+        let orphans = orphans_in(&root, &expected)?;
+        assert_eq!(vec![root.join("renamed/my-app")], orphans);
+
+        prune(&orphans)?;
+        assert!(!root.join("renamed").exists());
+        assert!(root.join("edge/my-app").exists());
+        assert!(root.join("hand-written/my-app").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_directories_within_the_output_path_are_removed() -> anyhow::Result<()> {
+        let temp = TempDir::new("prune-guard")?;
+        let dir = temp.path();
+        let root = dir.join("manifests");
+        for output in ["edge/my-app", "legacy/my-app", "foreign/my-app"] {
+            temp.write(&format!("manifests/{}/manifest.yaml", output), "---\n")?;
+        }
+        mark_owned(&root.join("edge/my-app"))?;
+        temp.write("manifests/foreign/my-app/notes.txt", "keep me")?;
+        std::fs::create_dir_all(dir.join("other"))?;
+
+        assert!(check_removable(&root, &root.join("edge/my-app")).is_ok());
+        assert!(check_removable(&root, &root.join("legacy/my-app")).is_ok());
+        assert!(check_removable(&root, &root.join("foreign/my-app")).is_err());
+        assert!(check_removable(&root, &root).is_err());
+        assert!(check_removable(&root, &root.join("../other")).is_err());
+
+        // the root of a repository must never be used as output path
+        std::fs::create_dir_all(dir.join(".git"))?;
+        assert!(check_removable(dir, &dir.join("manifests/edge")).is_err());
+        assert!(check_root(Path::new("/")).is_err());
+
+        Ok(())
+    }
+}
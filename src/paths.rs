@@ -0,0 +1,74 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Resolve `.` and `..` without accessing the file system, e.g. `a/./b/../c` becomes `a/c`
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Path of `path` relative to the directory `base`, like `realpath --relative-to`
+/// Both paths must be absolute; neither has to exist.
+pub fn relative(path: &Path, base: &Path) -> PathBuf {
+    let path = normalize(path);
+    let base = normalize(base);
+
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = base.components().collect();
+    let common = path_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative: PathBuf = base_components[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .collect();
+    relative.extend(&path_components[common..]);
+
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_made_relative() {
+        assert_eq!(
+            PathBuf::from("../charts/app"),
+            relative(Path::new("/repo/charts/./app"), Path::new("/repo/configs"))
+        );
+        assert_eq!(
+            PathBuf::from("values/edge.yaml"),
+            relative(
+                Path::new("/repo/configs/values/edge.yaml"),
+                Path::new("/repo/configs/")
+            )
+        );
+        assert_eq!(
+            PathBuf::from("."),
+            relative(Path::new("/repo/configs/x/.."), Path::new("/repo/configs"))
+        );
+    }
+}
@@ -0,0 +1,516 @@
+use crate::manifest;
+use anyhow::{anyhow, bail};
+use regex::Regex;
+use serde::Deserialize;
+use serde_yaml::Value;
+
+/// Declarative rule which every matching resource of a rendered manifest has to satisfy
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Name of the policy, used in messages and to refer to it from exceptions
+    pub name: String,
+
+    /// Kinds of resources the policy applies to; all kinds if omitted
+    pub kinds: Option<Vec<String>>,
+
+    /// Violations of an `error` policy fail the deployment, those of a `warning` policy are logged
+    pub severity: Option<Severity>,
+
+    /// Explanation added to every violation, e.g. how to fix it
+    pub message: Option<String>,
+
+    /// Assertions which all have to hold for a resource to satisfy the policy
+    pub assert: Vec<Assertion>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Assertion on the values selected by a JSONPath-style expression
+///
+/// Every selected value has to satisfy all of the given conditions. Values which do not exist
+/// are only checked by `exists`; the other conditions ignore them.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Assertion {
+    /// Expression selecting the values to check, e.g. `$.spec.template.spec.containers[*].image`
+    pub path: String,
+
+    /// Whether the selected values have to exist, or must not exist
+    pub exists: Option<bool>,
+
+    /// Value the selected values have to be equal to
+    pub equals: Option<Value>,
+
+    /// Regular expression the selected values have to match
+    pub matches: Option<String>,
+
+    /// Regular expression the selected values must not match
+    pub not_matches: Option<String>,
+}
+
+/// Exempt resources of a deployment from a policy
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyException {
+    /// Name of the policy
+    pub policy: String,
+
+    /// Resources to exempt as `Kind/name`, where either part may be `*`; all resources if omitted
+    pub resources: Option<Vec<String>>,
+}
+
+/// A resource which does not satisfy a policy
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+    pub policy: String,
+    pub severity: Severity,
+
+    /// Resource as `Kind/name`
+    pub resource: String,
+
+    pub message: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} violates {}: {}",
+            self.resource, self.policy, self.message
+        )
+    }
+}
+
+/// Single step of a path expression
+#[derive(Debug, PartialEq)]
+enum Segment {
+    /// `.key` or `['key']`
+    Key(String),
+
+    /// `[0]`
+    Index(usize),
+
+    /// `[*]` or `.*`; all items of a list or values of a mapping
+    Wildcard,
+
+    /// `..key`; the values of `key` anywhere below the current value
+    Descendant(String),
+}
+
+impl Policy {
+    /// Check that the policy can be evaluated; returns the field of the policy which is invalid
+    pub fn check(&self) -> Result<(), (String, anyhow::Error)> {
+        if self.assert.is_empty() {
+            return Err((
+                "assert".to_string(),
+                anyhow!("policy {} has no assertions", self.name),
+            ));
+        }
+
+        for (i, assertion) in self.assert.iter().enumerate() {
+            if let Err(err) = parse_path(&assertion.path) {
+                return Err((format!("assert[{}].path", i), err));
+            }
+
+            for (field, regex) in [
+                ("matches", &assertion.matches),
+                ("not_matches", &assertion.not_matches),
+            ] {
+                if let Some(regex) = regex {
+                    if let Err(err) = Regex::new(regex) {
+                        return Err((format!("assert[{}].{}", i, field), err.into()));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the policy applies to resources of the given kind
+    fn applies_to(&self, kind: &str) -> bool {
+        match &self.kinds {
+            Some(kinds) => kinds.iter().any(|k| k == kind),
+            None => true,
+        }
+    }
+}
+
+impl PolicyException {
+    /// Whether the exception covers the given resource of the given policy
+    fn covers(&self, policy: &str, kind: &str, name: &str) -> bool {
+        if self.policy != policy {
+            return false;
+        }
+
+        let resources = match &self.resources {
+            Some(resources) => resources,
+            None => return true,
+        };
+
+        resources.iter().any(|resource| {
+            let (k, n) = resource.split_once('/').unwrap_or((resource, "*"));
+            (k == "*" || k == kind) && (n == "*" || n == name)
+        })
+    }
+}
+
+/// Evaluate all policies on every resource of the manifest, skipping exempted resources
+pub fn evaluate(
+    policies: &[Policy],
+    exceptions: &[PolicyException],
+    manifest: &str,
+) -> anyhow::Result<Vec<Violation>> {
+    let mut violations = vec![];
+
+    for document in manifest::documents(manifest)? {
+        let kind = document["kind"].as_str().unwrap_or_default();
+        let name = document["metadata"]["name"].as_str().unwrap_or_default();
+
+        for policy in policies {
+            if !policy.applies_to(kind)
+                || exceptions
+                    .iter()
+                    .any(|e| e.covers(&policy.name, kind, name))
+            {
+                continue;
+            }
+
+            for assertion in &policy.assert {
+                for problem in check_assertion(assertion, &document)? {
+                    let message = match &policy.message {
+                        Some(message) => format!("{} ({})", problem, message),
+                        None => problem,
+                    };
+                    violations.push(Violation {
+                        policy: policy.name.clone(),
+                        severity: policy.severity.unwrap_or(Severity::Error),
+                        resource: format!("{}/{}", kind, name),
+                        message,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(violations)
+}
+
+/// Check a single assertion on a resource; returns a description of each failed condition
+fn check_assertion(assertion: &Assertion, document: &Value) -> anyhow::Result<Vec<String>> {
+    let mut problems = vec![];
+    let matches = assertion.matches.as_deref().map(Regex::new).transpose()?;
+    let not_matches = assertion
+        .not_matches
+        .as_deref()
+        .map(Regex::new)
+        .transpose()?;
+
+    for (location, value) in select(&parse_path(&assertion.path)?, document) {
+        let value = match (value, assertion.exists) {
+            (None, Some(true)) => {
+                problems.push(format!("{} is required", location));
+                continue;
+            }
+            (Some(_), Some(false)) => {
+                problems.push(format!("{} is not allowed", location));
+                continue;
+            }
+            (None, _) => continue,
+            (Some(value), _) => value,
+        };
+
+        if let Some(expected) = &assertion.equals {
+            if value != expected {
+                problems.push(format!(
+                    "{} is {}, expected {}",
+                    location,
+                    display(value),
+                    display(expected)
+                ));
+            }
+        }
+
+        if let Some(regex) = &matches {
+            if !regex.is_match(&display(value)) {
+                problems.push(format!(
+                    "{} is {}, which does not match {:?}",
+                    location,
+                    display(value),
+                    regex.as_str()
+                ));
+            }
+        }
+
+        if let Some(regex) = &not_matches {
+            if regex.is_match(&display(value)) {
+                problems.push(format!(
+                    "{} is {}, which must not match {:?}",
+                    location,
+                    display(value),
+                    regex.as_str()
+                ));
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Parse a path expression like `$.spec.containers[*]['image']` or `$..containers[*].image`
+fn parse_path(path: &str) -> anyhow::Result<Vec<Segment>> {
+    let mut segments = vec![];
+    let mut rest = match path.strip_prefix('$') {
+        Some(rest) => rest,
+        None => bail!("invalid path {:?}: must start with '$'", path),
+    };
+
+    // keys end at the next separator
+    let key = |s: &str| -> (String, usize) {
+        let end = s.find(['.', '[']).unwrap_or(s.len());
+        (s[..end].to_string(), end)
+    };
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            let (name, end) = key(after);
+            if name.is_empty() {
+                bail!("invalid path {:?}: missing key after '..'", path);
+            }
+            segments.push(Segment::Descendant(name));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let (name, end) = key(after);
+            match name.as_str() {
+                "" => bail!("invalid path {:?}: missing key after '.'", path),
+                "*" => segments.push(Segment::Wildcard),
+                _ => segments.push(Segment::Key(name)),
+            }
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after
+                .find(']')
+                .ok_or_else(|| anyhow!("invalid path {:?}: missing ']'", path))?;
+            let inner = after[..end].trim();
+            let quoted = inner
+                .strip_prefix('\'')
+                .and_then(|s| s.strip_suffix('\''))
+                .or_else(|| inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')));
+
+            segments.push(match (inner, quoted) {
+                ("*", _) => Segment::Wildcard,
+                (_, Some(name)) => Segment::Key(name.to_string()),
+                _ => Segment::Index(inner.parse().map_err(|_| {
+                    anyhow!("invalid path {:?}: {:?} is not an index", path, inner)
+                })?),
+            });
+            rest = &after[end + 1..];
+        } else {
+            bail!("invalid path {:?}: expected '.' or '[' at {:?}", path, rest);
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Select values by path; returns the location of each along with the value, if it exists
+/// Keys and indexes which do not exist are kept, so that `exists` can be checked for each item
+/// of a wildcard. Wildcards and descendants only select values which exist.
+fn select<'a>(segments: &[Segment], document: &'a Value) -> Vec<(String, Option<&'a Value>)> {
+    let mut selected = vec![("$".to_string(), Some(document))];
+
+    for segment in segments {
+        let mut next = vec![];
+
+        for (location, value) in selected {
+            match segment {
+                Segment::Key(name) => next.push((
+                    format!("{}.{}", location, name),
+                    value.and_then(|v| v.get(name.as_str())),
+                )),
+                Segment::Index(index) => next.push((
+                    format!("{}[{}]", location, index),
+                    value.and_then(|v| v.get(*index)),
+                )),
+                Segment::Wildcard => match value {
+                    Some(Value::Sequence(items)) => next.extend(
+                        items
+                            .iter()
+                            .enumerate()
+                            .map(|(i, item)| (format!("{}[{}]", location, i), Some(item))),
+                    ),
+                    Some(Value::Mapping(entries)) => {
+                        next.extend(entries.iter().map(|(key, item)| {
+                            (format!("{}.{}", location, display(key)), Some(item))
+                        }))
+                    }
+                    _ => {}
+                },
+                Segment::Descendant(name) => {
+                    if let Some(value) = value {
+                        descendants(name, &location, value, &mut next);
+                    }
+                }
+            }
+        }
+
+        selected = next;
+    }
+
+    selected
+}
+
+/// Collect the values of the given key in the value and everything below it
+fn descendants<'a>(
+    name: &str,
+    location: &str,
+    value: &'a Value,
+    found: &mut Vec<(String, Option<&'a Value>)>,
+) {
+    match value {
+        Value::Mapping(entries) => {
+            for (key, item) in entries {
+                let location = format!("{}.{}", location, display(key));
+                if key.as_str() == Some(name) {
+                    found.push((location.clone(), Some(item)));
+                }
+                descendants(name, &location, item, found);
+            }
+        }
+        Value::Sequence(items) => {
+            for (i, item) in items.iter().enumerate() {
+                descendants(name, &format!("{}[{}]", location, i), item, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Format a value for messages and regular expressions; strings are used as they are
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        _ => serde_yaml::to_string(value)
+            .unwrap_or_default()
+            .trim_start_matches("---")
+            .trim()
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const MANIFEST: &str = r#"---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: my-app
+spec:
+  template:
+    spec:
+      initContainers:
+        - name: init
+          image: busybox:latest
+      containers:
+        - name: app
+          image: my-app:1.2.3
+          resources:
+            limits:
+              memory: 128Mi
+        - name: sidecar
+          image: envoy:latest
+      volumes:
+        - name: host
+          hostPath:
+            path: /var/run
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: my-app
+"#;
+
+    fn policies() -> Vec<Policy> {
+        serde_yaml::from_str(
+            r#"
+- name: no-latest-tag
+  message: pin the image
+  assert:
+    - path: $..containers[*].image
+      not_matches: ":latest$"
+- name: resource-limits
+  kinds: [Deployment]
+  severity: warning
+  assert:
+    - path: $.spec.template.spec.containers[*].resources.limits
+      exists: true
+- name: no-host-path
+  assert:
+    - path: $.spec.template.spec.volumes[*].hostPath
+      exists: false
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn paths_are_parsed() {
+        assert_eq!(
+            vec![
+                Segment::Descendant("metadata".to_string()),
+                Segment::Key("labels".to_string()),
+                Segment::Key("app.kubernetes.io/name".to_string()),
+                Segment::Index(0),
+                Segment::Wildcard,
+            ],
+            parse_path("$..metadata.labels['app.kubernetes.io/name'][0].*").unwrap()
+        );
+        assert!(parse_path("$.spec[").is_err());
+        assert!(parse_path("$.spec[first]").is_err());
+        assert!(parse_path("spec").is_err());
+    }
+
+    #[test]
+    fn violations_are_reported_per_resource() {
+        let violations = evaluate(&policies(), &[], MANIFEST).unwrap();
+        let messages: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+
+        assert_eq!(
+            vec![
+                "Deployment/my-app violates no-latest-tag: $.spec.template.spec.containers[1].image is envoy:latest, which must not match \":latest$\" (pin the image)",
+                "Deployment/my-app violates resource-limits: $.spec.template.spec.containers[1].resources.limits is required",
+                "Deployment/my-app violates no-host-path: $.spec.template.spec.volumes[0].hostPath is not allowed",
+            ],
+            messages
+        );
+        assert_eq!(Severity::Warning, violations[1].severity);
+    }
+
+    #[test]
+    fn exceptions_exempt_resources() {
+        let exceptions: Vec<PolicyException> = serde_yaml::from_str(
+            r#"
+- policy: no-host-path
+  resources: ["Deployment/my-app"]
+- policy: resource-limits
+- policy: no-latest-tag
+  resources: ["StatefulSet/*"]
+"#,
+        )
+        .unwrap();
+
+        let violations = evaluate(&policies(), &exceptions, MANIFEST).unwrap();
+        let policies: Vec<&str> = violations.iter().map(|v| v.policy.as_str()).collect();
+
+        assert_eq!(vec!["no-latest-tag"], policies);
+    }
+}
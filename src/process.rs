@@ -0,0 +1,238 @@
+use anyhow::{bail, Context};
+use log::debug;
+#[cfg(unix)]
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use subprocess::PopenConfig;
+use subprocess::{ExitStatus, Popen, Redirection};
+
+/// Interval in which running commands are checked for timeouts and interruption
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set once Ctrl-C was pressed; running commands are killed and no further commands are started
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Output of a command which ran to completion
+pub struct Captured {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Handle Ctrl-C by killing the running command instead of exiting right away
+/// This gives the caller the chance to write reports and leave no partial output behind.
+pub fn handle_interrupts() -> anyhow::Result<()> {
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst))
+        .context("can not install Ctrl-C handler")
+}
+
+/// Whether Ctrl-C was pressed
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Fail if Ctrl-C was pressed, so that no further work is started
+pub fn check_interrupted() -> anyhow::Result<()> {
+    if interrupted() {
+        bail!("interrupted");
+    }
+
+    Ok(())
+}
+
+/// Parse a duration like `90s`, `5m` or `1h 30m`
+pub fn parse_timeout(timeout: &str) -> anyhow::Result<Duration> {
+    humantime::parse_duration(timeout)
+        .with_context(|| format!("invalid timeout {:?}; use e.g. '90s' or '5m'", timeout))
+}
+
+/// Run the given command through the shell and capture its output
+///
+/// The given variables are added to the environment of the command. If the command does not
+/// finish within the timeout, or Ctrl-C is pressed, it is killed along with all processes it
+/// started, e.g. the commands of a pipeline. On Windows, only the shell itself can be killed.
+pub fn run_shell(
+    cmd: &str,
+    env: &[(String, String)],
+    timeout: Option<Duration>,
+) -> anyhow::Result<Captured> {
+    check_interrupted()?;
+
+    let mut process = spawn(cmd, env).with_context(|| format!("can not run {:?}", cmd))?;
+    let started = Instant::now();
+
+    let mut stdout = vec![];
+    let mut stderr = vec![];
+    let mut communicator = process.communicate_start(None).limit_time(POLL_INTERVAL);
+
+    loop {
+        match communicator.read() {
+            Ok((out, err)) => {
+                stdout.extend(out.unwrap_or_default());
+                stderr.extend(err.unwrap_or_default());
+                break;
+            }
+            Err(err) if err.error.kind() == ErrorKind::TimedOut => {
+                let (out, err) = err.capture;
+                stdout.extend(out.unwrap_or_default());
+                stderr.extend(err.unwrap_or_default());
+                abort_if_needed(&mut process, cmd, started, timeout)?;
+            }
+            Err(err) => {
+                kill(&mut process);
+                return Err(err.error).with_context(|| format!("can not read output of {:?}", cmd));
+            }
+        }
+    }
+
+    // output is closed, but the shell may not have exited yet
+    let status = loop {
+        if let Some(status) = process.wait_timeout(POLL_INTERVAL)? {
+            break status;
+        }
+        abort_if_needed(&mut process, cmd, started, timeout)?;
+    };
+
+    Ok(Captured {
+        status,
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+    })
+}
+
+/// Write the file by renaming a temporary file next to it into place
+/// Readers never see a partially written file, and the temporary file is removed on failure.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let result = std::fs::write(&temporary, contents)
+        .and_then(|_| std::fs::rename(&temporary, path))
+        .with_context(|| format!("can not write {:?}", path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+
+    result
+}
+
+/// Kill the process if Ctrl-C was pressed or it ran into the timeout
+fn abort_if_needed(
+    process: &mut Popen,
+    cmd: &str,
+    started: Instant,
+    timeout: Option<Duration>,
+) -> anyhow::Result<()> {
+    if interrupted() {
+        kill(process);
+        bail!("interrupted while running:\n    {}", cmd);
+    }
+
+    if let Some(timeout) = timeout {
+        if started.elapsed() >= timeout {
+            kill(process);
+            bail!(
+                "timed out after {} while running:\n    {}",
+                humantime::format_duration(timeout),
+                cmd
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Kill the process and everything it started, then reap it
+fn kill(process: &mut Popen) {
+    #[cfg(unix)]
+    if let Some(pid) = process.pid() {
+        debug!("killing process group {}", pid);
+        // SAFETY: plain syscall; the process group was created for this process only
+        unsafe {
+            libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        debug!("killing process {:?}", process.pid());
+        let _ = process.kill();
+    }
+
+    let _ = process.wait();
+}
+
+/// Start the command through `sh` in a process group of its own, so that the whole pipeline can be killed at once
+#[cfg(unix)]
+fn spawn(cmd: &str, env: &[(String, String)]) -> subprocess::Result<Popen> {
+    let mut environment: Vec<(OsString, OsString)> = std::env::vars_os().collect();
+    environment.extend(env.iter().map(|(k, v)| (k.into(), v.into())));
+
+    let config = PopenConfig {
+        stdout: Redirection::Pipe,
+        stderr: Redirection::Pipe,
+        env: Some(environment),
+        setpgid: true,
+        ..Default::default()
+    };
+
+    Popen::create(&["sh", "-c", cmd], config)
+}
+
+/// Start the command through the platform's shell
+#[cfg(not(unix))]
+fn spawn(cmd: &str, env: &[(String, String)]) -> subprocess::Result<Popen> {
+    subprocess::Exec::shell(cmd)
+        .env_extend(env)
+        .stdout(Redirection::Pipe)
+        .stderr(Redirection::Pipe)
+        .popen()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    #[cfg(unix)]
+    fn output_and_environment_are_passed() {
+        let captured = run_shell(
+            "echo $GREETING && echo oops >&2",
+            &[("GREETING".to_string(), "hello".to_string())],
+            None,
+        )
+        .unwrap();
+
+        assert!(captured.status.success());
+        assert_eq!("hello\n", captured.stdout);
+        assert_eq!("oops\n", captured.stderr);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn pipelines_are_killed_on_timeout() {
+        let started = Instant::now();
+        let err = run_shell("sleep 10 | cat", &[], Some(Duration::from_millis(200)))
+            .err()
+            .unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            "timed out after 200ms while running:\n    sleep 10 | cat",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn timeouts_are_parsed() {
+        assert_eq!(Duration::from_secs(90), parse_timeout("90s").unwrap());
+        assert_eq!(Duration::from_secs(330), parse_timeout("5m 30s").unwrap());
+        assert!(parse_timeout("soon").is_err());
+    }
+}
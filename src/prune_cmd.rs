@@ -0,0 +1,36 @@
+use crate::output;
+use crate::PruneCmdOpts;
+use log::info;
+
+/// The prune sub command removes output directories left behind by removed or renamed deployments.
+pub struct PruneCmd {
+    opts: PruneCmdOpts,
+}
+
+impl PruneCmd {
+    /// Create sub command struct to prune the output paths of the given input file(s)
+    pub fn new(opts: PruneCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to run the pruning
+    /// will print the orphaned output directories when running dry
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("prune options: {:?}", self.opts);
+
+        let orphans = output::find_orphans(&self.opts.input_files)?;
+        if orphans.is_empty() {
+            info!("no orphaned output directories found");
+            return Ok(());
+        }
+
+        if self.opts.dry_run {
+            for orphan in &orphans {
+                println!("{}", orphan.display());
+            }
+            return Ok(());
+        }
+
+        output::prune(&orphans)
+    }
+}
@@ -1,23 +1,91 @@
+use crate::cache::Cache;
+use crate::changes::ChangeSet;
 use crate::config::{Config, ValidationOpts};
+use crate::junit::JUnitReport;
+use crate::manifest;
+use crate::output;
+use crate::policy::{self, Policy, PolicyException, Severity};
+use crate::process;
+use crate::report::{Report, ReportEntry, Status};
+use crate::schema::{self, SchemaValidator};
+use crate::transform::Transforms;
+use crate::watch::{diff_summary, Watcher};
 use crate::RenderCmdOpts;
 use anyhow::{bail, Context};
 use indexmap::map::IndexMap;
-use log::{debug, info};
+use log::{debug, error, info, warn};
 use regex::Regex;
+use semver::{Version, VersionReq};
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::path::PathBuf;
-use subprocess::{Exec, Redirection};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 /// Special name used in the commands map of a plan when a helm dependency update is requested
 const PRE_CMD_DEPENDENCY_UPDATE: &str = "helm-dependency-update";
 
+/// Reason recorded for deployments which are not rendered because rendering stopped before them
+const NOT_RENDERED_AFTER_FAILURE: &str = "not rendered after earlier failure";
+
+/// Error returned when a `helm` (or pipe) command fails
+/// Keeps the captured output around, so that it can be attached to reports.
+#[derive(Debug)]
+pub struct CommandFailed {
+    pub command: String,
+    pub stderr: String,
+    pub stdout: String,
+}
+
+impl std::fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed while running:\n    {}", self.command)?;
+
+        if !self.stderr.is_empty() {
+            write!(f, "\n\nstderr:\n    {}", self.stderr)?;
+        }
+
+        if !self.stdout.is_empty() {
+            write!(f, "\n\nstdout:\n    {}", self.stdout)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
 pub struct RenderCmd {
     opts: RenderCmdOpts,
+
+    /// Files changed since the revision passed via `--changed-since`; populated by `run`
+    changes: Option<ChangeSet>,
+
+    /// Render cache located in `--cache-dir`; populated by `run`
+    cache: Option<Cache>,
+
+    /// Output of `helm version --short` per helm binary, so that each binary is queried only once per run
+    helm_versions: RefCell<HashMap<String, String>>,
+
+    /// Validator for `--validate-manifests`; populated by `run`
+    schemas: Option<SchemaValidator>,
+
+    /// Manifests rendered so far, if they are kept in memory instead of being written; see `capture`
+    captured: Option<RefCell<Vec<RenderedManifest>>>,
+}
+
+/// Manifest of a single deployment, rendered to memory by `RenderCmd::capture`
+pub struct RenderedManifest {
+    /// Configuration file the deployment is defined in, as passed to the templexer
+    pub config: PathBuf,
+
+    pub deployment: String,
+    pub manifest: String,
 }
 
 /// Plan which contains all commands to be executed
 /// Can be skipped if the config is disabled at the top level
-/// Disabled deployments are not added to the plan
+/// Disabled deployments are not added to the plan, but recorded as skipped
 struct Plan {
     /// Skip this plan; set to true if the config is disabled on the top level
     skip: bool,
@@ -26,59 +94,394 @@ struct Plan {
     /// This field uses a IndexMap to guarantee order of iteration.
     pre_commands: IndexMap<String, Vec<String>>,
 
-    /// Commands to be executed on the host system
+    /// Commands to be executed on the host system, in order of the deployments in the config
     /// key: deployment.name
-    /// value: vector of strings containing the complete command, e.g. vec!["helm", "template", ...]
-    commands: HashMap<String, (PathBuf, Vec<String>)>,
+    commands: IndexMap<String, PlannedDeployment>,
+
+    /// Deployments which are not rendered, in order of the deployments in the config
+    /// key: deployment.name
+    /// value: whether the deployment is skipped or disabled, and why
+    skipped: IndexMap<String, (Status, String)>,
+
+    /// Helm binary all commands of the plan are run with
+    helm_binary: String,
+
+    /// Time after which pre-commands are aborted
+    timeout: Option<Duration>,
+
+    /// Configuration file the plan was created from, as passed to the templexer
+    config_file: PathBuf,
+
+    /// Root of the output directories of all deployments, relative to the config file
+    output_path: PathBuf,
+}
+
+/// A single deployment to be rendered as part of a plan
+#[derive(Debug, PartialEq)]
+struct PlannedDeployment {
+    /// Fully qualified path of the manifest to write
+    output: PathBuf,
+
+    /// Vector of strings containing the complete command, e.g. vec!["helm", "template", ...]
+    command: Vec<String>,
+
+    /// Files the manifest is rendered from: the chart directory and all value files
+    inputs: Vec<PathBuf>,
+
+    /// Built-in transforms of the config combined with the ones of the deployment
+    transforms: Transforms,
+
+    /// Environment variables describing the deployment, passed to the command and its pipes
+    env: Vec<(String, String)>,
+
+    /// Time after which the command and its pipes are aborted
+    timeout: Option<Duration>,
+
+    /// Kubernetes version to validate the manifest against, if any
+    kube_version: Option<String>,
+
+    /// Absolute paths of the directories containing schemas of custom resources
+    crd_schemas: Vec<PathBuf>,
+
+    /// Policies of the config and the exceptions of the deployment
+    policies: Vec<Policy>,
+    policy_exceptions: Vec<PolicyException>,
 }
 
 impl RenderCmd {
     /// Create sub command struct to render deployments of the given input file(s)
     pub fn new(opts: RenderCmdOpts) -> Self {
-        Self { opts }
+        Self {
+            opts,
+            changes: None,
+            cache: None,
+            helm_versions: Default::default(),
+            schemas: None,
+            captured: None,
+        }
     }
 
     /// Main entry point to run the rendering process
     /// will return nothing on the happy path and descriptive errors on failure
-    pub fn run(&self) -> anyhow::Result<()> {
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        self.prepare()?;
+
+        if !self.opts.watch {
+            self.render_all()?;
+            self.log_cache_stats();
+            return self.prune();
+        }
+
+        let mut watcher = Watcher::new(&self.opts.input_files);
+        if let Err(err) = self.render_all() {
+            error!("{:#}", err);
+        }
+
+        loop {
+            info!("watching for changes (press ctrl-c to stop)");
+            let changed = match watcher.wait_for_changes() {
+                Some(changed) => changed,
+                None => {
+                    info!("interrupted; stopped watching");
+                    return Ok(());
+                }
+            };
+            debug!("changed files: {:#?}", changed);
+
+            self.changes = Some(ChangeSet::from_files(changed));
+            match self.render_all() {
+                Ok(summary) if summary.is_empty() => info!("no deployments affected"),
+                Ok(summary) => {
+                    info!("summary:");
+                    for line in summary {
+                        info!(" - {}", line);
+                    }
+                }
+                Err(err) => error!("{:#}", err),
+            }
+            self.log_cache_stats();
+            if let Err(err) = self.prune() {
+                error!("{:#}", err);
+            }
+        }
+    }
+
+    /// Render all deployments of the input files to memory instead of writing their manifests
+    /// Used by sub commands which inspect manifests; `--stdout` and `--watch` are ignored.
+    pub fn capture(mut self) -> anyhow::Result<Vec<RenderedManifest>> {
+        self.opts.stdout = false;
+        self.opts.watch = false;
+        self.opts.prune = false;
+        self.captured = Some(Default::default());
+
+        self.prepare()?;
+        self.render_all()?;
+        self.log_cache_stats();
+
+        Ok(self.captured.map(|c| c.into_inner()).unwrap_or_default())
+    }
+
+    /// Remove output directories of deployments which no longer exist, if requested
+    fn prune(&self) -> anyhow::Result<()> {
+        if !self.opts.prune {
+            return Ok(());
+        }
+
+        let orphans = output::find_orphans(&self.opts.input_files)?;
+        output::prune(&orphans)
+    }
+
+    /// Set up everything requested by the options before rendering
+    fn prepare(&mut self) -> anyhow::Result<()> {
         debug!("render options: {:?}", self.opts);
+        process::handle_interrupts()?;
 
-        for file in &self.opts.input_files {
-            info!("processing {:?}", file);
+        if let Some(base) = &self.opts.changed_since {
+            info!("looking up changes since {:?}", base);
+            self.changes = Some(ChangeSet::from_git(base)?);
+        }
+
+        if let Some(dir) = &self.opts.cache_dir {
+            debug!("using render cache {:?}", dir);
+            self.cache = Some(Cache::open(dir)?);
+        }
+
+        // the working directory is switched for every config file, so resolve paths right away
+        if let Some(binary) = &self.opts.helm_binary {
+            if binary.components().count() > 1 {
+                self.opts.helm_binary = Some(
+                    binary
+                        .canonicalize()
+                        .with_context(|| format!("can not find helm binary {:?}", binary))?,
+                );
+            }
+        }
 
-            let opts = ValidationOpts {
-                config_file: Some(file.clone()),
-                ..Default::default()
+        if self.opts.validate_manifests {
+            let dir = match &self.opts.schema_dir {
+                Some(dir) => std::env::current_dir()?.join(dir),
+                None => schema::default_schema_dir(),
             };
-            let cfg = Config::load(file)?;
-            cfg.switch_working_directory(file)?.validate(&opts)?;
+            debug!("validating manifests against schemas in {:?}", dir);
+            self.schemas = Some(SchemaValidator::new(dir, self.opts.ignore_missing_schemas));
+        }
 
-            let plan = self.plan(&cfg)?;
+        Ok(())
+    }
+
+    /// Render all input files; returns a summary line per rendered deployment in watch mode
+    /// Reports requested via `--report` and `--junit` are written even if rendering fails.
+    fn render_all(&self) -> anyhow::Result<Vec<String>> {
+        let mut report = Report::default();
+        let result = self.render_files(&mut report);
+
+        if let Some(file) = &self.opts.report {
+            report.write(file)?;
+            info!("report written to {:?}", file);
+        }
 
-            if plan.skip {
-                info!("config is disabled (skipped)");
+        if let Some(file) = &self.opts.junit {
+            JUnitReport::from(&report).write(file)?;
+            info!("JUnit report written to {:?}", file);
+        }
+
+        result
+    }
+
+    /// Render all input files, recording the outcome of every deployment in the report
+    /// Rendering stops at the first failure; the deployments of the remaining files are recorded
+    /// as skipped. In watch mode, failures are logged and the remaining files are rendered, so
+    /// that a single broken file does not hold back the others.
+    fn render_files(&self, report: &mut Report) -> anyhow::Result<Vec<String>> {
+        let mut summary = vec![];
+        let mut failure = None;
+
+        for file in &self.opts.input_files {
+            if failure.is_some() {
+                skip_config(file, report);
                 continue;
             }
+            info!("processing {:?}", file);
+
+            match self.render_file(file, report) {
+                Ok(rendered) => summary.extend(
+                    rendered
+                        .into_iter()
+                        .map(|line| format!("{}: {}", file.display(), line)),
+                ),
+                Err(err) if self.opts.watch => error!("{}: {:#}", file.display(), err),
+                Err(err) => failure = Some(err),
+            }
+        }
+
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(summary),
+        }
+    }
+
+    /// Load and render a single input file
+    fn render_file(&self, file: &Path, report: &mut Report) -> anyhow::Result<Vec<String>> {
+        let cfg = match Config::load(file) {
+            Ok(cfg) => cfg,
+            Err(err) => {
+                report
+                    .entries
+                    .push(ReportEntry::new(file, None, Status::Failed).fail(&err));
+                return Err(err);
+            }
+        };
+        cfg.switch_working_directory(file)?;
+        let result = self.render_config(&cfg, report);
+        cfg.reset_working_directory()?;
+
+        result
+    }
+
+    /// Validate, plan and render a single configuration file
+    /// Make sure to switch the working directory and reset it afterwards using `switch_working_directory` and `reset_working_directory`.
+    fn render_config(&self, cfg: &Config, report: &mut Report) -> anyhow::Result<Vec<String>> {
+        let opts = ValidationOpts {
+            config_file: Some(cfg.config_file.clone()),
+            ..Default::default()
+        };
+
+        let plan = match cfg
+            .validate(&opts)
+            .and_then(|_| self.plan(cfg))
+            .and_then(|plan| self.check_helm_version(cfg, &plan).map(|_| plan))
+        {
+            Ok(plan) => plan,
+            Err(err) => {
+                report
+                    .entries
+                    .push(ReportEntry::new(&cfg.config_file, None, Status::Failed).fail(&err));
+                return Err(err);
+            }
+        };
+
+        for (deployment, (status, reason)) in &plan.skipped {
+            report.entries.push(ReportEntry::skipped(
+                &plan.config_file,
+                deployment,
+                *status,
+                reason,
+            ));
+        }
+
+        if plan.skip {
+            info!("config is disabled (skipped)");
+            return Ok(vec![]);
+        }
+
+        self.exec_plan(&plan, report)
+    }
+
+    /// Determine the helm binary to use for the given config
+    /// `--helm-binary` takes precedence over the config; paths in the config are made absolute,
+    /// so make sure to switch the working directory to the config file before calling this.
+    fn helm_binary(&self, cfg: &Config) -> anyhow::Result<String> {
+        if let Some(binary) = &self.opts.helm_binary {
+            return Ok(binary.to_string_lossy().to_string());
+        }
 
-            self.exec_plan(&plan)?;
+        let binary = match cfg.helm.as_ref().and_then(|h| h.binary.as_ref()) {
+            Some(binary) => PathBuf::from(binary),
+            None => return Ok("helm".to_string()),
+        };
+
+        if binary.components().count() > 1 {
+            let absolute = binary
+                .canonicalize()
+                .with_context(|| format!("can not find helm binary {:?}", binary))?;
+            return Ok(absolute.to_string_lossy().to_string());
+        }
 
-            cfg.reset_working_directory()?;
+        Ok(binary.to_string_lossy().to_string())
+    }
+
+    /// Output of `helm version --short` for the given binary, e.g. `v3.12.0+gc9f554d`
+    fn helm_version(&self, binary: &str) -> anyhow::Result<String> {
+        if let Some(version) = self.helm_versions.borrow().get(binary) {
+            return Ok(version.clone());
+        }
+
+        let mut output = vec![];
+        self.run_helm(
+            &format!("{} version --short", binary),
+            &[],
+            self.opts.timeout,
+            &mut output,
+        )?;
+        let version = String::from_utf8_lossy(&output).trim().to_string();
+        debug!("{} is helm {}", binary, version);
+
+        self.helm_versions
+            .borrow_mut()
+            .insert(binary.to_string(), version.clone());
+
+        Ok(version)
+    }
+
+    /// Make sure the helm binary of the plan satisfies the version constraint of the config
+    fn check_helm_version(&self, cfg: &Config, plan: &Plan) -> anyhow::Result<()> {
+        let constraint = match cfg.helm.as_ref().and_then(|h| h.version.as_ref()) {
+            Some(constraint) if !plan.skip => constraint,
+            _ => return Ok(()),
+        };
+        let requirement = VersionReq::parse(constraint)
+            .with_context(|| format!("invalid helm version constraint {:?}", constraint))?;
+
+        let output = self.helm_version(&plan.helm_binary)?;
+        let version = Version::parse(output.trim_start_matches('v')).with_context(|| {
+            format!(
+                "can not parse version {:?} reported by {}",
+                output, plan.helm_binary
+            )
+        })?;
+
+        if !requirement.matches(&version) {
+            bail!(
+                "{} is helm {}, but {:?} requires helm {}",
+                plan.helm_binary,
+                output,
+                cfg.config_file,
+                constraint
+            );
         }
 
         Ok(())
     }
 
+    /// Log how many deployments were served from the render cache, if enabled
+    fn log_cache_stats(&self) {
+        if let Some(cache) = &self.cache {
+            let (hits, misses) = cache.hits_and_misses();
+            info!("render cache: {} hit(s), {} miss(es)", hits, misses);
+        }
+    }
+
     /// Create a plan of commands to execute
     fn plan(&self, cfg: &Config) -> anyhow::Result<Plan> {
         let mut plan = Plan {
             skip: false,
             pre_commands: Default::default(),
             commands: Default::default(),
+            skipped: Default::default(),
+            helm_binary: self.helm_binary(cfg)?,
+            timeout: self.timeout(&cfg.timeout)?.or(self.opts.timeout),
+            config_file: cfg.config_file.clone(),
+            output_path: cfg.output_path.clone(),
         };
 
         if let Some(enabled) = cfg.enabled {
             if !enabled {
                 plan.skip = true;
+                for d in &cfg.deployments {
+                    let reason = "config is disabled".to_string();
+                    plan.skipped
+                        .insert(d.name.to_owned(), (Status::Disabled, reason));
+                }
                 return Ok(plan);
             }
         }
@@ -93,7 +496,7 @@ impl RenderCmd {
 
         if self.opts.update_dependencies {
             let cmd = vec![
-                "helm".to_string(),
+                plan.helm_binary.clone(),
                 "dependencies".to_string(),
                 "update".to_string(),
                 chart.to_string(),
@@ -109,7 +512,7 @@ impl RenderCmd {
             .collect();
 
         let mut base_cmd = vec![
-            "helm".to_string(),
+            plan.helm_binary.clone(),
             "template".to_string(),
             cfg.release_name.clone(),
             chart.to_string(),
@@ -125,11 +528,19 @@ impl RenderCmd {
             base_cmd.extend(opts.to_owned());
         }
 
-        match &self.opts.additional_options {
-            Some(opts) => base_cmd.extend(opts.clone()),
-            None => (),
+        if let Some(opts) = &self.opts.additional_options {
+            base_cmd.extend(opts.clone())
         }
 
+        // the working directory is switched for every config file, so keep paths absolute
+        let working_directory = std::env::current_dir()?;
+        let crd_schemas: Vec<PathBuf> = cfg
+            .crd_schemas
+            .iter()
+            .flatten()
+            .map(|dir| working_directory.join(dir))
+            .collect();
+
         for d in &cfg.deployments {
             if self.opts.filter.is_some()
                 && !self.is_name_filtered(
@@ -141,17 +552,45 @@ impl RenderCmd {
                 )?
             {
                 info!(" - (skip) {}", d.name);
+                let reason = "not matched by --filter".to_string();
+                plan.skipped
+                    .insert(d.name.to_owned(), (Status::Skipped, reason));
                 continue;
             }
             if let Some(enabled) = d.enabled {
                 if !enabled {
                     info!(" - (skip) {}", d.name);
+                    let reason = "deployment is disabled".to_string();
+                    plan.skipped
+                        .insert(d.name.to_owned(), (Status::Disabled, reason));
+                    continue;
+                }
+            }
+            if let Some(changes) = &self.changes {
+                let reasons = changes.reasons(cfg, d)?;
+                if reasons.is_empty() {
+                    info!(" - (skip) {}", d.name);
+                    let reason = "not affected by changes".to_string();
+                    plan.skipped
+                        .insert(d.name.to_owned(), (Status::Skipped, reason));
+                    if self.opts.explain {
+                        info!("     no changes affect this deployment");
+                    }
                     continue;
                 }
+                if self.opts.explain {
+                    info!(" - (selected) {}", d.name);
+                    for reason in reasons {
+                        info!("     {}", reason);
+                    }
+                }
             }
 
             let mut cmd = base_cmd.clone();
 
+            let mut inputs = vec![cfg.chart.clone()];
+            inputs.extend(cfg.values.iter().chain(d.values.iter()).flatten().cloned());
+
             let values: Vec<String> = self
                 .get_values_as_strings(&d.values)?
                 .iter()
@@ -160,39 +599,91 @@ impl RenderCmd {
 
             cmd.extend(values);
 
-            match &d.additional_options {
-                Some(opts) => cmd.extend(opts.clone()),
-                None => (),
+            if let Some(opts) = &d.additional_options {
+                cmd.extend(opts.clone())
             }
 
             let mut release_name = cfg.release_name.clone();
-            match &d.release_name {
-                Some(n) => release_name = n.to_owned(),
-                None => (),
+            if let Some(n) = &d.release_name {
+                release_name = n.to_owned()
             }
             cmd[2] = release_name.to_owned();
 
-            let mut fully_qualified_output = cfg
-                .output_path
-                .join(&d.name)
-                .join(release_name)
-                .join("manifest");
-            fully_qualified_output.set_extension("yaml");
+            let fully_qualified_output = cfg.manifest_path(d);
 
-            if let Some(opts) = &self.opts.pipe {
-                let pipe_command: Vec<String> = opts.iter().map(|p| format!("| {}", p)).collect();
-                cmd.extend(pipe_command)
-            }
+            let transforms = cfg
+                .transforms
+                .clone()
+                .unwrap_or_default()
+                .merge(&d.transforms.clone().unwrap_or_default());
+
+            // pipes of the config run first, then the ones of the deployment, then `--pipe`
+            let pipes = cfg
+                .pipe
+                .iter()
+                .chain(d.pipe.iter())
+                .chain(self.opts.pipe.iter())
+                .flatten();
+            cmd.extend(pipes.map(|p| format!("| {}", p)));
+
+            let kube_version = self
+                .opts
+                .kube_version
+                .as_ref()
+                .or(d.kube_version.as_ref())
+                .or(cfg.kube_version.as_ref())
+                .cloned();
+
+            // the working directory was switched already, so resolve the file as it was given
+            let config_file = cfg.original_working_directory.join(&cfg.config_file);
+            let config_file = config_file.canonicalize().unwrap_or(config_file);
+            let env = vec![
+                ("HELM_TEMPLEXER_DEPLOYMENT", d.name.clone()),
+                ("HELM_TEMPLEXER_RELEASE_NAME", release_name),
+                (
+                    "HELM_TEMPLEXER_NAMESPACE",
+                    cfg.namespace.clone().unwrap_or_default(),
+                ),
+                (
+                    "HELM_TEMPLEXER_CONFIG_FILE",
+                    config_file.to_string_lossy().to_string(),
+                ),
+                (
+                    "HELM_TEMPLEXER_OUTPUT_PATH",
+                    fully_qualified_output.to_string_lossy().to_string(),
+                ),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect();
 
-            plan.commands
-                .insert(d.name.to_owned(), (fully_qualified_output, cmd));
+            plan.commands.insert(
+                d.name.to_owned(),
+                PlannedDeployment {
+                    output: fully_qualified_output,
+                    command: cmd,
+                    inputs,
+                    transforms,
+                    env,
+                    timeout: self.timeout(&d.timeout)?.or(plan.timeout),
+                    kube_version,
+                    crd_schemas: crd_schemas.clone(),
+                    policies: cfg.policies.clone().unwrap_or_default(),
+                    policy_exceptions: d.policy_exceptions.clone().unwrap_or_default(),
+                },
+            );
         }
 
         Ok(plan)
     }
 
-    /// Execute the commands in the given plan
-    fn exec_plan(&self, plan: &Plan) -> anyhow::Result<()> {
+    /// Execute the commands in the given plan, recording the outcome of every deployment in the report
+    /// Execution stops at the first failure; the remaining deployments are recorded as skipped.
+    /// In watch mode, a line summarizing the changes to the manifest is returned per deployment
+    fn exec_plan(&self, plan: &Plan, report: &mut Report) -> anyhow::Result<Vec<String>> {
+        let mut summary = vec![];
+        let mut failure = None;
+
         if !&plan.pre_commands.is_empty() {
             info!("pre-commands:");
 
@@ -205,50 +696,320 @@ impl RenderCmd {
                     cmd.join(" ")
                 );
 
-                self.run_helm(&cmd.join(" "), std::io::sink())?;
+                if let Err(err) = self.run_helm(&cmd.join(" "), &[], plan.timeout, std::io::sink())
+                {
+                    report
+                        .entries
+                        .push(ReportEntry::new(&plan.config_file, None, Status::Failed).fail(&err));
+                    failure = Some(err);
+                    break;
+                }
             }
         }
 
         if !&plan.commands.is_empty() {
             info!("deployments:");
 
-            for (deployment, cmd) in &plan.commands {
+            for (deployment, planned) in &plan.commands {
+                if failure.is_some() {
+                    report.entries.push(ReportEntry::skipped(
+                        &plan.config_file,
+                        deployment,
+                        Status::Skipped,
+                        NOT_RENDERED_AFTER_FAILURE,
+                    ));
+                    continue;
+                }
                 info!(" - {}", deployment);
 
                 debug!(
                     "executing planned command for deployment {}:\n \t {:#?}",
                     deployment,
-                    cmd.1.join(" ")
+                    planned.command.join(" ")
                 );
 
-                let output_parent = cmd
-                    .0
-                    .parent()
-                    .ok_or_else(|| anyhow::anyhow!("missing parent. this should never happen"))?;
-
-                if output_parent.exists() {
-                    debug!("cleaning up output path: {:?}", output_parent);
-                    std::fs::remove_dir_all(output_parent)?;
+                let started = Instant::now();
+                let mut entry =
+                    ReportEntry::new(&plan.config_file, Some(deployment), Status::Rendered);
+                entry.argv = planned.command.clone();
+
+                let result = self.exec_deployment(plan, deployment, planned, &mut entry);
+                entry.duration_ms = started.elapsed().as_millis();
+
+                match result {
+                    Ok(line) => {
+                        report.entries.push(entry);
+                        summary.extend(line);
+                    }
+                    Err(err) => {
+                        report.entries.push(entry.fail(&err));
+                        failure = Some(err);
+                    }
                 }
-                std::fs::create_dir_all(output_parent)?;
+            }
+        }
+
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(summary),
+        }
+    }
+
+    /// Render a single planned deployment and write its manifest
+    /// Details about the manifest are added to the given report entry; in watch mode, a line
+    /// summarizing the changes to the manifest is returned.
+    fn exec_deployment(
+        &self,
+        plan: &Plan,
+        deployment: &str,
+        planned: &PlannedDeployment,
+        entry: &mut ReportEntry,
+    ) -> anyhow::Result<Option<String>> {
+        let (mut manifest, cached) = self.render(deployment, planned)?;
+        if !planned.transforms.is_empty() {
+            manifest = planned
+                .transforms
+                .apply(&String::from_utf8_lossy(&manifest))?
+                .into_bytes();
+        }
+
+        if let Some(validator) = &self.schemas {
+            self.validate_manifest(validator, deployment, planned, &manifest)?;
+        }
+        if !planned.policies.is_empty() {
+            self.enforce_policies(deployment, planned, &manifest)?;
+        }
+
+        entry.cached = cached;
+        entry.sha256 = Some(manifest::sha256(&manifest));
+        if self.opts.report.is_some() {
+            // pipe commands may turn the output into something else than YAML
+            entry.resource_count = manifest::documents(&String::from_utf8_lossy(&manifest))
+                .ok()
+                .map(|d| d.len());
+        }
+
+        if self.opts.stdout {
+            self.write_to_stdout(&plan.config_file, deployment, &manifest)?;
+            return Ok(None);
+        }
+
+        if let Some(captured) = &self.captured {
+            captured.borrow_mut().push(RenderedManifest {
+                config: plan.config_file.clone(),
+                deployment: deployment.to_string(),
+                manifest: String::from_utf8_lossy(&manifest).to_string(),
+            });
+            return Ok(None);
+        }
+
+        let previous = if self.opts.watch {
+            std::fs::read_to_string(&planned.output).ok()
+        } else {
+            None
+        };
+
+        self.write_manifest(&plan.output_path, &planned.output, &manifest)?;
+        entry.output_path = Some(std::env::current_dir()?.join(&planned.output));
+
+        if !self.opts.watch {
+            return Ok(None);
+        }
+
+        let current = String::from_utf8_lossy(&manifest);
+        Ok(Some(format!(
+            "{}: {}",
+            deployment,
+            diff_summary(previous.as_deref(), &current)
+        )))
+    }
+
+    /// Validate every resource of the manifest against its schema; fails listing all problems found
+    fn validate_manifest(
+        &self,
+        validator: &SchemaValidator,
+        deployment: &str,
+        planned: &PlannedDeployment,
+        manifest: &[u8],
+    ) -> anyhow::Result<()> {
+        let kube_version = match &planned.kube_version {
+            Some(version) => version,
+            None => bail!(
+                "no Kubernetes version to validate deployment {} against; set `kube_version` or use --kube-version",
+                deployment
+            ),
+        };
+
+        let problems = validator
+            .validate(
+                &String::from_utf8_lossy(manifest),
+                kube_version,
+                &planned.crd_schemas,
+            )
+            .with_context(|| format!("can not validate manifest of deployment {}", deployment))?;
+
+        if !problems.is_empty() {
+            bail!(
+                "manifest of deployment {} does not match the Kubernetes {} schemas:\n    {}",
+                deployment,
+                kube_version,
+                problems.join("\n    ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Evaluate the policies of the config on the manifest
+    /// Violations of warning policies are logged; violations of error policies fail the deployment.
+    fn enforce_policies(
+        &self,
+        deployment: &str,
+        planned: &PlannedDeployment,
+        manifest: &[u8],
+    ) -> anyhow::Result<()> {
+        let violations = policy::evaluate(
+            &planned.policies,
+            &planned.policy_exceptions,
+            &String::from_utf8_lossy(manifest),
+        )
+        .with_context(|| format!("can not evaluate policies for deployment {}", deployment))?;
+
+        let mut errors = vec![];
+        for violation in violations {
+            match violation.severity {
+                Severity::Warning => warn!("   {}", violation),
+                Severity::Error => errors.push(violation.to_string()),
+            }
+        }
 
-                let output_file =
-                    std::fs::File::create(&cmd.0).context("can not create output file")?;
-                let output_writer = std::io::BufWriter::new(output_file);
+        if !errors.is_empty() {
+            bail!(
+                "manifest of deployment {} violates policies:\n    {}",
+                deployment,
+                errors.join("\n    ")
+            );
+        }
 
-                self.run_helm(&cmd.1.join(" "), output_writer)?;
+        Ok(())
+    }
+
+    /// Render the manifest of a planned deployment, either by running its command or from the cache
+    /// Returns the manifest and whether it was restored from the cache.
+    fn render(
+        &self,
+        deployment: &str,
+        planned: &PlannedDeployment,
+    ) -> anyhow::Result<(Vec<u8>, bool)> {
+        let mut manifest = vec![];
+
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => {
+                self.run_planned(planned, &mut manifest)?;
+                return Ok((manifest, false));
             }
+        };
+
+        // pipes may use the environment, so it is part of the key just like the command
+        let mut command = planned.command.clone();
+        command.extend(planned.env.iter().map(|(k, v)| format!("{}={}", k, v)));
+        let helm_version = self.helm_version(&planned.command[0])?;
+        let key = cache.key(&helm_version, &command, &planned.inputs)?;
+        if let Some(cached) = cache.get(&key)? {
+            debug!("restored manifest for deployment {} from cache", deployment);
+            return Ok((cached, true));
+        }
+
+        self.run_planned(planned, &mut manifest)?;
+        cache.put(&key, &manifest)?;
+
+        Ok((manifest, false))
+    }
+
+    /// Write the manifest to the given output path, replacing the previous contents of its parent
+    /// The parent is only removed if it is safe to do so, see `output::check_removable`.
+    fn write_manifest(&self, root: &Path, output: &Path, manifest: &[u8]) -> anyhow::Result<()> {
+        let output_parent = output
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("missing parent. this should never happen"))?;
+
+        if output_parent.exists() {
+            output::check_removable(root, output_parent)?;
+            debug!("cleaning up output path: {:?}", output_parent);
+            std::fs::remove_dir_all(output_parent)?;
+        }
+        std::fs::create_dir_all(output_parent)?;
+        output::mark_owned(output_parent)?;
+
+        process::write_atomically(output, manifest).context("can not create output file")?;
+
+        Ok(())
+    }
+
+    /// Write the manifest to stdout as part of a multi-document stream
+    /// Each deployment starts with a document marker and a comment identifying its origin.
+    fn write_to_stdout(
+        &self,
+        config_file: &Path,
+        deployment: &str,
+        manifest: &[u8],
+    ) -> anyhow::Result<()> {
+        let manifest = String::from_utf8_lossy(manifest);
+
+        // helm starts its output with a document marker, which would leave the header on its own
+        let manifest = manifest
+            .strip_prefix("---\n")
+            .unwrap_or_else(|| manifest.trim_start_matches('\n'));
+
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "---")?;
+        writeln!(
+            stdout,
+            "# helm-templexer: config={} deployment={}",
+            config_file.display(),
+            deployment
+        )?;
+        stdout.write_all(manifest.as_bytes())?;
+        if !manifest.ends_with('\n') {
+            writeln!(stdout)?;
         }
 
         Ok(())
     }
 
-    /// Run `helm` commands
+    /// Run the command of a planned deployment, including its pipes
+    fn run_planned(
+        &self,
+        planned: &PlannedDeployment,
+        output: impl std::io::Write,
+    ) -> anyhow::Result<()> {
+        self.run_helm(
+            &planned.command.join(" "),
+            &planned.env,
+            planned.timeout,
+            output,
+        )
+    }
+
+    /// Parse a timeout of the config or one of its deployments
+    fn timeout(&self, timeout: &Option<String>) -> anyhow::Result<Option<Duration>> {
+        timeout.as_deref().map(process::parse_timeout).transpose()
+    }
+
+    /// Run `helm` commands, adding the given variables to their environment
+    /// Commands are killed once they exceed the timeout, or Ctrl-C is pressed.
     ///
     /// With special result handling as `helm` could exit 0 while logging `exit status 1`.
     /// It is unclear if the issue is actually resolved, see
     /// https://github.com/helm/helm/issues/8268
-    fn run_helm(&self, cmd: &str, mut output: impl std::io::Write) -> anyhow::Result<()> {
+    fn run_helm(
+        &self,
+        cmd: &str,
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+        mut output: impl std::io::Write,
+    ) -> anyhow::Result<()> {
         // `helm` logs that it wanted to exit 1 but actually exits 0:
         //
         //   ❯ helm version --client
@@ -267,32 +1028,22 @@ impl RenderCmd {
         //
         // The issue is reported and open https://github.com/helm/helm/issues/8268
         //   as of 2020-07-26
-        let result = Exec::shell(cmd)
-            .stdout(Redirection::Pipe)
-            .stderr(Redirection::Pipe)
-            .capture()?;
-
-        debug!("stdout:\n{}", result.stdout_str());
-        debug!("stderr:\n{}", result.stderr_str());
+        let result = process::run_shell(cmd, env, timeout)?;
 
-        if !result.exit_status.success() || result.stdout_str().contains("exit status 1") {
-            let mut error_msg = format!("failed while running:\n    {cmd}");
+        debug!("stdout:\n{}", result.stdout);
+        debug!("stderr:\n{}", result.stderr);
 
-            let stderr = result.stderr_str();
-            if !stderr.is_empty() {
-                error_msg.push_str(&format!("\n\nstderr:\n    {stderr}"));
+        if !result.status.success() || result.stdout.contains("exit status 1") {
+            return Err(CommandFailed {
+                command: cmd.to_string(),
+                stderr: result.stderr,
+                stdout: result.stdout,
             }
-
-            let stdout = result.stdout_str();
-            if !stdout.is_empty() {
-                error_msg.push_str(&format!("\n\nstdout:\n    {stdout}"));
-            }
-
-            bail!(error_msg);
+            .into());
         }
 
         output
-            .write_all(result.stdout_str().as_bytes())
+            .write_all(result.stdout.as_bytes())
             .context("can not write to output")?;
 
         Ok(())
@@ -319,10 +1070,33 @@ impl RenderCmd {
     }
 }
 
+/// Record the deployments of a configuration file which is not rendered after an earlier failure
+/// If the file can not be loaded, the file itself is recorded instead.
+fn skip_config(file: &Path, report: &mut Report) {
+    let deployments = match Config::load(file) {
+        Ok(cfg) => cfg.deployments.into_iter().map(|d| d.name).collect(),
+        Err(_) => vec![],
+    };
+
+    if deployments.is_empty() {
+        let mut entry = ReportEntry::new(file, None, Status::Skipped);
+        entry.skip_reason = Some(NOT_RENDERED_AFTER_FAILURE.to_string());
+        report.entries.push(entry);
+    }
+    for deployment in deployments {
+        report.entries.push(ReportEntry::skipped(
+            file,
+            &deployment,
+            Status::Skipped,
+            NOT_RENDERED_AFTER_FAILURE,
+        ));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::Deployment;
+    use crate::config::{Deployment, Helm};
     use pretty_assertions::assert_eq;
 
     /// Help function to abstract the construction of `Config` for test cases
@@ -331,14 +1105,22 @@ mod tests {
         Config {
             version: "v2".to_string(),
             enabled: Option::from(true),
+            helm: None,
             chart: Default::default(),
             namespace: None,
             release_name: "".to_string(),
             output_path: Default::default(),
             additional_options: None,
             values: None,
+            pipe: None,
+            timeout: None,
+            transforms: None,
+            kube_version: None,
+            crd_schemas: None,
+            policies: None,
             deployments: vec![],
             original_working_directory: Default::default(),
+            config_file: Default::default(),
         }
     }
 
@@ -352,7 +1134,26 @@ mod tests {
                 update_dependencies: false,
                 filter: None,
                 pipe: None,
+                changed_since: None,
+                explain: false,
+                cache_dir: None,
+                watch: false,
+                stdout: false,
+                report: None,
+                junit: None,
+                helm_binary: None,
+                timeout: None,
+                validate_manifests: false,
+                schema_dir: None,
+                kube_version: None,
+                ignore_missing_schemas: false,
+                prune: false,
             },
+            changes: None,
+            cache: None,
+            helm_versions: Default::default(),
+            schemas: None,
+            captured: None,
         }
     }
 
@@ -365,6 +1166,11 @@ mod tests {
             release_name: None,
             additional_options: None,
             values: None,
+            pipe: None,
+            timeout: None,
+            transforms: None,
+            kube_version: None,
+            policy_exceptions: None,
         }
     }
 
@@ -406,7 +1212,115 @@ mod tests {
 
         let got = res.commands.get("edge").unwrap();
 
-        assert_eq!(expected_helm_cmd, got.1)
+        assert_eq!(expected_helm_cmd, got.command)
+    }
+
+    #[test]
+    fn deployments_are_planned_in_order_of_appearance() {
+        let mut cfg = get_config();
+        cfg.chart = PathBuf::from("charts/some-chart");
+        cfg.release_name = "some-release".to_string();
+        cfg.output_path = PathBuf::from("manifests");
+
+        let names = vec!["prod", "edge", "stage", "dev"];
+        cfg.deployments = names
+            .iter()
+            .map(|n| {
+                let mut d = get_deployment();
+                d.name = n.to_string();
+                d
+            })
+            .collect();
+
+        let cmd = get_cmd();
+        let res = cmd.plan(&cfg).unwrap();
+        let got: Vec<&str> = res.commands.keys().map(|k| k.as_str()).collect();
+
+        assert_eq!(names, got);
+    }
+
+    #[test]
+    fn deployments_after_a_failure_are_recorded_as_skipped() {
+        let mut cfg = get_config();
+        cfg.chart = PathBuf::from("charts/some-chart");
+        cfg.release_name = "some-release".to_string();
+        cfg.output_path = PathBuf::from("manifests");
+        cfg.deployments = ["prod", "edge", "stage"]
+            .iter()
+            .map(|n| {
+                let mut d = get_deployment();
+                d.name = n.to_string();
+                d
+            })
+            .collect();
+
+        let mut cmd = get_cmd();
+        cmd.opts.helm_binary = Some(PathBuf::from("/nonexistent/helm"));
+        let plan = cmd.plan(&cfg).unwrap();
+        let mut report = Report::default();
+
+        assert!(cmd.exec_plan(&plan, &mut report).is_err());
+
+        let got: Vec<(Option<&str>, Status, Option<&str>)> = report
+            .entries
+            .iter()
+            .map(|e| (e.deployment.as_deref(), e.status, e.skip_reason.as_deref()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Some("prod"), Status::Failed, None),
+                (
+                    Some("edge"),
+                    Status::Skipped,
+                    Some(NOT_RENDERED_AFTER_FAILURE)
+                ),
+                (
+                    Some("stage"),
+                    Status::Skipped,
+                    Some(NOT_RENDERED_AFTER_FAILURE)
+                ),
+            ],
+            got
+        );
+    }
+
+    #[test]
+    fn remaining_configs_are_recorded_as_skipped() {
+        let mut report = Report::default();
+        skip_config(Path::new("tests/data/config_example.yaml"), &mut report);
+        skip_config(Path::new("tests/data/does-not-exist.yaml"), &mut report);
+
+        let got: Vec<Option<&str>> = report
+            .entries
+            .iter()
+            .map(|e| e.deployment.as_deref())
+            .collect();
+        assert_eq!(
+            vec![
+                Some("edge-eu-w4"),
+                Some("next-edge-eu-w4"),
+                Some("stage-eu-w4"),
+                Some("prod-eu-w4"),
+                None
+            ],
+            got
+        );
+        assert!(report.entries.iter().all(|e| e.status == Status::Skipped));
+    }
+
+    #[test]
+    fn failing_configs_do_not_stop_watch_mode() {
+        let mut cmd = get_cmd();
+        cmd.opts.watch = true;
+        cmd.opts.input_files = vec![
+            PathBuf::from("tests/data/does-not-exist.yaml"),
+            PathBuf::from("tests/data/does-not-exist-either.yaml"),
+        ];
+        let mut report = Report::default();
+
+        assert!(cmd.render_files(&mut report).unwrap().is_empty());
+        assert_eq!(2, report.entries.len());
+        assert!(report.entries.iter().all(|e| e.status == Status::Failed));
     }
 
     #[test]
@@ -426,6 +1340,64 @@ mod tests {
         assert_eq!(None, res.commands.get("edge"));
     }
 
+    #[test]
+    fn skipped_deployments_are_recorded_with_reason() {
+        let mut cfg = get_config();
+        cfg.chart = PathBuf::from("charts/some-chart");
+        cfg.release_name = "some-release".to_string();
+        cfg.output_path = PathBuf::from("manifests");
+
+        let mut edge = get_deployment();
+        edge.name = "edge".to_string();
+        edge.enabled = Option::from(false);
+        let mut prod = get_deployment();
+        prod.name = "prod".to_string();
+        let mut stage = get_deployment();
+        stage.name = "stage".to_string();
+        cfg.deployments = vec![edge, prod, stage];
+
+        let mut cmd = get_cmd();
+        cmd.opts.filter = Option::from("edge|stage".to_string());
+        let res = cmd.plan(&cfg).unwrap();
+
+        assert_eq!(
+            Some(&(Status::Disabled, "deployment is disabled".to_string())),
+            res.skipped.get("edge")
+        );
+        assert_eq!(
+            Some(&(Status::Skipped, "not matched by --filter".to_string())),
+            res.skipped.get("prod")
+        );
+        assert_eq!(None, res.skipped.get("stage"));
+    }
+
+    #[test]
+    fn deployment_transforms_are_combined_with_config_transforms() {
+        let mut cfg = get_config();
+        cfg.chart = PathBuf::from("charts/some-chart");
+        cfg.release_name = "some-release".to_string();
+        cfg.output_path = PathBuf::from("manifests");
+        cfg.transforms = Some(Transforms {
+            drop_comments: Some(true),
+            sort: Some(true),
+            ..Default::default()
+        });
+
+        let mut deployment = get_deployment();
+        deployment.name = "edge".to_string();
+        deployment.transforms = Some(Transforms {
+            sort: Some(false),
+            ..Default::default()
+        });
+        cfg.deployments = vec![deployment];
+
+        let res = get_cmd().plan(&cfg).unwrap();
+        let transforms = &res.commands.get("edge").unwrap().transforms;
+
+        assert_eq!(Some(true), transforms.drop_comments);
+        assert_eq!(Some(false), transforms.sort);
+    }
+
     #[test]
     fn deployment_can_override_release_name() {
         let mut cfg = get_config();
@@ -448,7 +1420,7 @@ mod tests {
 
         let got = res.commands.get("edge").unwrap();
 
-        assert_eq!(expected_helm_cmd, got.1);
+        assert_eq!(expected_helm_cmd, got.command);
     }
 
     #[test]
@@ -480,7 +1452,7 @@ mod tests {
 
         let got = res.commands.get("edge").unwrap();
 
-        assert_eq!(expected_helm_cmd, got.1);
+        assert_eq!(expected_helm_cmd, got.command);
     }
 
     #[test]
@@ -548,7 +1520,7 @@ mod tests {
             .collect();
         let got = res.commands.get("edge_eu_w4_deployment").unwrap();
 
-        assert_eq!(expected_helm_cmd, got.1);
+        assert_eq!(expected_helm_cmd, got.command);
         assert_eq!(res.commands.len(), 1);
     }
 
@@ -613,9 +1585,9 @@ mod tests {
         let got_eu_w4 = res.commands.get("prod_eu_w4_deployment").unwrap();
         let got_us_c1 = res.commands.get("prod_us_c1_deployment").unwrap();
 
-        assert_eq!(prod_as_e1_deployment_expected_helm_cmd, got_as_e1.1);
-        assert_eq!(prod_eu_w4_deployment_expected_helm_cmd, got_eu_w4.1);
-        assert_eq!(prod_us_c1_deployment_expected_helm_cmd, got_us_c1.1);
+        assert_eq!(prod_as_e1_deployment_expected_helm_cmd, got_as_e1.command);
+        assert_eq!(prod_eu_w4_deployment_expected_helm_cmd, got_eu_w4.command);
+        assert_eq!(prod_us_c1_deployment_expected_helm_cmd, got_us_c1.command);
         assert_eq!(res.commands.len(), 3);
     }
 
@@ -681,9 +1653,9 @@ mod tests {
         let got_stage = res.commands.get("stage_eu_w4_deployment").unwrap();
         let got_prod = res.commands.get("prod_eu_w4_deployment").unwrap();
 
-        assert_eq!(edge_eu_w4_deployment_expected_helm_cmd, got_edge.1);
-        assert_eq!(prod_eu_w4_deployment_expected_helm_cmd, got_prod.1);
-        assert_eq!(stage_eu_w4_deployment_expected_helm_cmd, got_stage.1);
+        assert_eq!(edge_eu_w4_deployment_expected_helm_cmd, got_edge.command);
+        assert_eq!(prod_eu_w4_deployment_expected_helm_cmd, got_prod.command);
+        assert_eq!(stage_eu_w4_deployment_expected_helm_cmd, got_stage.command);
         assert_eq!(res.commands.len(), 3);
     }
 
@@ -716,7 +1688,7 @@ mod tests {
 
         let got_edge = res.commands.get("edge").unwrap();
 
-        assert_eq!(edge_expected_helm_cmd, got_edge.1);
+        assert_eq!(edge_expected_helm_cmd, got_edge.command);
         assert_eq!(res.commands.len(), 1);
     }
 
@@ -750,7 +1722,125 @@ mod tests {
 
         let got_edge = res.commands.get("edge").unwrap();
 
-        assert_eq!(edge_expected_helm_cmd, got_edge.1);
+        assert_eq!(edge_expected_helm_cmd, got_edge.command);
         assert_eq!(res.commands.len(), 1);
     }
+
+    #[test]
+    fn pipes_of_config_deployment_and_cli_are_combined_in_order() {
+        let mut cfg = get_config();
+        cfg.chart = PathBuf::from("charts/some-chart");
+        cfg.namespace = Option::from("default".to_string());
+        cfg.release_name = "some-release".to_string();
+        cfg.output_path = PathBuf::from("manifests");
+        cfg.config_file = PathBuf::from("deploy/my-app.yaml");
+        cfg.original_working_directory = PathBuf::from("/repo");
+        cfg.pipe = Option::from(vec!["kbld -f -".to_string()]);
+
+        let mut edge = get_deployment();
+        edge.name = "edge".to_string();
+        edge.pipe = Option::from(vec!["grep image".to_string()]);
+
+        cfg.deployments = vec![edge];
+
+        let mut cmd = get_cmd();
+        cmd.opts.pipe = Option::from(vec!["cat".to_string()]);
+
+        let res = cmd.plan(&cfg).unwrap();
+        let got_edge = res.commands.get("edge").unwrap();
+
+        assert_eq!(
+            vec!["| kbld -f -", "| grep image", "| cat"],
+            got_edge.command[got_edge.command.len() - 3..].to_vec()
+        );
+        assert_eq!(
+            vec![
+                ("HELM_TEMPLEXER_DEPLOYMENT".to_string(), "edge".to_string()),
+                (
+                    "HELM_TEMPLEXER_RELEASE_NAME".to_string(),
+                    "some-release".to_string()
+                ),
+                (
+                    "HELM_TEMPLEXER_NAMESPACE".to_string(),
+                    "default".to_string()
+                ),
+                (
+                    "HELM_TEMPLEXER_CONFIG_FILE".to_string(),
+                    "/repo/deploy/my-app.yaml".to_string()
+                ),
+                (
+                    "HELM_TEMPLEXER_OUTPUT_PATH".to_string(),
+                    "manifests/edge/some-release/manifest.yaml".to_string()
+                ),
+            ],
+            got_edge.env
+        );
+    }
+
+    #[test]
+    fn helm_binary_of_cli_takes_precedence_over_config() {
+        let mut cfg = get_config();
+        cfg.chart = PathBuf::from("charts/some-chart");
+        cfg.release_name = "some-release".to_string();
+        cfg.output_path = PathBuf::from("manifests");
+        cfg.deployments = vec![get_deployment()];
+
+        let mut cmd = get_cmd();
+        assert_eq!("helm", cmd.plan(&cfg).unwrap().helm_binary);
+
+        cfg.helm = Some(Helm {
+            binary: Some("helm3".to_string()),
+            version: None,
+        });
+        let res = cmd.plan(&cfg).unwrap();
+        assert_eq!("helm3", res.helm_binary);
+        assert_eq!("helm3", res.commands.get("").unwrap().command[0]);
+
+        cmd.opts.helm_binary = Some(PathBuf::from("helm-3.12"));
+        assert_eq!("helm-3.12", cmd.plan(&cfg).unwrap().helm_binary);
+    }
+
+    #[test]
+    fn deployment_timeout_takes_precedence_over_config_and_cli() {
+        let mut cfg = get_config();
+        cfg.chart = PathBuf::from("charts/some-chart");
+        cfg.release_name = "some-release".to_string();
+        cfg.output_path = PathBuf::from("manifests");
+        cfg.deployments = vec![
+            Deployment {
+                name: "edge".to_string(),
+                timeout: Some("5m".to_string()),
+                ..get_deployment()
+            },
+            Deployment {
+                name: "prod".to_string(),
+                ..get_deployment()
+            },
+        ];
+
+        let mut cmd = get_cmd();
+        cmd.opts.timeout = Some(Duration::from_secs(30));
+        let res = cmd.plan(&cfg).unwrap();
+        assert_eq!(Some(Duration::from_secs(30)), res.timeout);
+        assert_eq!(
+            Some(Duration::from_secs(300)),
+            res.commands.get("edge").unwrap().timeout
+        );
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            res.commands.get("prod").unwrap().timeout
+        );
+
+        cfg.timeout = Some("1m".to_string());
+        let res = cmd.plan(&cfg).unwrap();
+        assert_eq!(Some(Duration::from_secs(60)), res.timeout);
+        assert_eq!(
+            Some(Duration::from_secs(300)),
+            res.commands.get("edge").unwrap().timeout
+        );
+        assert_eq!(
+            Some(Duration::from_secs(60)),
+            res.commands.get("prod").unwrap().timeout
+        );
+    }
 }
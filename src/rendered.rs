@@ -0,0 +1,79 @@
+use crate::config::Config;
+use crate::render_cmd::{RenderCmd, RenderedManifest};
+use crate::{ManifestSourceOpts, RenderCmdOpts};
+use anyhow::Context;
+use log::{info, warn};
+use regex::Regex;
+
+/// Collect the manifests of all enabled deployments of the input files
+/// Depending on the options, deployments are rendered to memory or the manifests written by a
+/// previous render are read.
+pub fn manifests(opts: &ManifestSourceOpts) -> anyhow::Result<Vec<RenderedManifest>> {
+    if opts.render {
+        let render_opts = RenderCmdOpts {
+            input_files: opts.input_files.clone(),
+            filter: opts.filter.clone(),
+            helm_binary: opts.helm_binary.clone(),
+            ..Default::default()
+        };
+
+        return RenderCmd::new(render_opts).capture();
+    }
+
+    let filter = match &opts.filter {
+        Some(pattern) => Some(Regex::new(pattern).context("invalid --filter pattern")?),
+        None => None,
+    };
+    let mut manifests = vec![];
+
+    for file in &opts.input_files {
+        let cfg = Config::load(file)?;
+        cfg.switch_working_directory(file)?;
+        let found = read_manifests(&cfg, filter.as_ref());
+        cfg.reset_working_directory()?;
+
+        manifests.extend(found?);
+    }
+
+    if manifests.is_empty() {
+        info!("no manifests found");
+    }
+
+    Ok(manifests)
+}
+
+/// Read the manifests of all enabled deployments of the given config which match the filter
+/// Make sure to switch the working directory to the config file before calling this.
+fn read_manifests(cfg: &Config, filter: Option<&Regex>) -> anyhow::Result<Vec<RenderedManifest>> {
+    let mut manifests = vec![];
+
+    if !cfg.enabled.unwrap_or(true) {
+        return Ok(manifests);
+    }
+
+    for d in &cfg.deployments {
+        if !d.enabled.unwrap_or(true) || matches!(filter, Some(f) if !f.is_match(&d.name)) {
+            continue;
+        }
+
+        let path = cfg.manifest_path(d);
+        if !path.exists() {
+            warn!(
+                "{}: no manifest for deployment {} at {:?}; render it first or use --render",
+                cfg.config_file.display(),
+                d.name,
+                path
+            );
+            continue;
+        }
+
+        manifests.push(RenderedManifest {
+            config: cfg.config_file.clone(),
+            deployment: d.name.clone(),
+            manifest: std::fs::read_to_string(&path)
+                .with_context(|| format!("can not read {:?}", path))?,
+        });
+    }
+
+    Ok(manifests)
+}
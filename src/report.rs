@@ -0,0 +1,173 @@
+use crate::render_cmd::CommandFailed;
+use anyhow::Context;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Outcome of a single deployment
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Rendered,
+    Skipped,
+    Disabled,
+    Failed,
+}
+
+/// Machine-readable record of a render run, written via `render --report`
+#[derive(Serialize, Debug, Default)]
+pub struct Report {
+    pub entries: Vec<ReportEntry>,
+}
+
+/// Record of a single deployment, or of a config which failed before its deployments were planned
+#[derive(Serialize, Debug)]
+pub struct ReportEntry {
+    /// Configuration file as passed to the templexer
+    pub config: PathBuf,
+
+    /// Name of the deployment; empty if the config itself failed to load or validate
+    pub deployment: Option<String>,
+
+    pub status: Status,
+
+    /// Why the deployment was skipped or disabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+
+    /// Complete command used to render the deployment, including pipe commands
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub argv: Vec<String>,
+
+    /// Absolute path the manifest was written to; empty when writing to stdout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_path: Option<PathBuf>,
+
+    /// Whether the manifest was restored from the render cache
+    pub cached: bool,
+
+    /// Time spent rendering and writing the manifest
+    pub duration_ms: u128,
+
+    /// Number of resources in the rendered manifest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_count: Option<usize>,
+
+    /// Hex-encoded sha256 checksum of the rendered manifest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    /// Error message of a failed deployment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// Captured stderr of the failed command
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+}
+
+impl ReportEntry {
+    /// Create an entry without any rendering details
+    pub fn new(config: &Path, deployment: Option<&str>, status: Status) -> Self {
+        Self {
+            config: config.to_path_buf(),
+            deployment: deployment.map(String::from),
+            status,
+            skip_reason: None,
+            argv: vec![],
+            output_path: None,
+            cached: false,
+            duration_ms: 0,
+            resource_count: None,
+            sha256: None,
+            error: None,
+            stderr: None,
+        }
+    }
+
+    /// Create an entry for a deployment that was not rendered
+    pub fn skipped(config: &Path, deployment: &str, status: Status, reason: &str) -> Self {
+        let mut entry = Self::new(config, Some(deployment), status);
+        entry.skip_reason = Some(reason.to_string());
+
+        entry
+    }
+
+    /// Mark the entry as failed with the given error
+    /// The stderr of failed commands is attached separately.
+    pub fn fail(mut self, err: &anyhow::Error) -> Self {
+        self.status = Status::Failed;
+        self.error = Some(format!("{:#}", err));
+        self.stderr = err
+            .downcast_ref::<CommandFailed>()
+            .map(|e| e.stderr.clone());
+
+        self
+    }
+}
+
+impl Report {
+    /// Write the report as pretty-printed JSON to the given file
+    pub fn write(&self, file: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(file, json + "\n")
+            .with_context(|| format!("can not write report to {:?}", file))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn command_failures_keep_their_stderr() {
+        let err = anyhow::Error::new(CommandFailed {
+            command: "helm template my-app chart".to_string(),
+            stderr: "Error: chart not found".to_string(),
+            stdout: "".to_string(),
+        });
+
+        let entry =
+            ReportEntry::new(Path::new("my-app.yaml"), Some("edge"), Status::Rendered).fail(&err);
+
+        assert_eq!(Status::Failed, entry.status);
+        assert_eq!(Some("Error: chart not found".to_string()), entry.stderr);
+        assert_eq!(
+            Some(
+                "failed while running:\n    helm template my-app chart\n\nstderr:\n    Error: chart not found"
+                    .to_string()
+            ),
+            entry.error
+        );
+    }
+
+    #[test]
+    fn entries_serialize_without_empty_fields() {
+        let report = Report {
+            entries: vec![ReportEntry::skipped(
+                Path::new("my-app.yaml"),
+                "edge",
+                Status::Disabled,
+                "deployment is disabled",
+            )],
+        };
+
+        let json = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(
+            serde_json::json!({
+                "entries": [{
+                    "config": "my-app.yaml",
+                    "deployment": "edge",
+                    "status": "disabled",
+                    "skip_reason": "deployment is disabled",
+                    "cached": false,
+                    "duration_ms": 0
+                }]
+            }),
+            json
+        );
+    }
+}
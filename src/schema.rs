@@ -0,0 +1,303 @@
+use crate::manifest;
+use anyhow::{bail, Context};
+use jsonschema::JSONSchema;
+use log::debug;
+use serde_yaml::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Validates rendered manifests against JSON schemas stored on disk, without access to a cluster
+///
+/// Kubernetes schemas are looked up in the layout of
+/// https://github.com/yannh/kubernetes-json-schema, e.g.
+/// `<schema_dir>/v1.29.0-standalone-strict/deployment-apps-v1.json`. Schemas of custom resources
+/// are looked up in the layout of https://github.com/datreeio/CRDs-catalog, e.g.
+/// `<crd_dir>/monitoring.coreos.com/servicemonitor_v1.json`, and take precedence.
+pub struct SchemaValidator {
+    /// Directory containing the Kubernetes schemas of all versions
+    schema_dir: PathBuf,
+
+    /// Accept resources for which no schema can be found
+    ignore_missing: bool,
+
+    /// Compiled schemas by file, so that each schema is only read and compiled once per run
+    /// `None` if the file does not exist.
+    schemas: RefCell<HashMap<PathBuf, Option<JSONSchema>>>,
+}
+
+impl SchemaValidator {
+    pub fn new(schema_dir: PathBuf, ignore_missing: bool) -> Self {
+        Self {
+            schema_dir,
+            ignore_missing,
+            schemas: Default::default(),
+        }
+    }
+
+    /// Validate every document of the manifest
+    /// Returns a description of each problem found, prefixed with the resource it was found in.
+    pub fn validate(
+        &self,
+        manifest: &str,
+        kube_version: &str,
+        crd_dirs: &[PathBuf],
+    ) -> anyhow::Result<Vec<String>> {
+        let mut problems = vec![];
+
+        for document in manifest::documents(manifest)? {
+            if document.is_null() {
+                continue;
+            }
+
+            let api_version = document["apiVersion"].as_str().unwrap_or_default();
+            let kind = document["kind"].as_str().unwrap_or_default();
+            let name = document["metadata"]["name"].as_str().unwrap_or("<unnamed>");
+            let resource = format!("{} {} ({})", kind, name, api_version);
+
+            if api_version.is_empty() || kind.is_empty() {
+                problems.push(format!("{}: apiVersion and kind are required", resource));
+                continue;
+            }
+
+            let candidates = self.candidates(api_version, kind, kube_version, crd_dirs);
+            let mut found = false;
+            for file in &candidates {
+                if self.load(file)? {
+                    problems.extend(
+                        self.check(file, &document)?
+                            .into_iter()
+                            .map(|p| format!("{}: {}", resource, p)),
+                    );
+                    found = true;
+                    break;
+                }
+            }
+
+            if !found && !self.ignore_missing {
+                let searched: Vec<String> =
+                    candidates.iter().map(|c| c.display().to_string()).collect();
+                problems.push(format!(
+                    "{}: no schema found; searched {}",
+                    resource,
+                    searched.join(", ")
+                ));
+            }
+        }
+
+        Ok(problems)
+    }
+
+    /// Schema files which may describe the given resource, in order of precedence
+    fn candidates(
+        &self,
+        api_version: &str,
+        kind: &str,
+        kube_version: &str,
+        crd_dirs: &[PathBuf],
+    ) -> Vec<PathBuf> {
+        let kind = kind.to_lowercase();
+        let (group, version) = match api_version.split_once('/') {
+            Some((group, version)) => (group, version),
+            None => ("", api_version),
+        };
+
+        let mut candidates = vec![];
+        if !group.is_empty() {
+            candidates.extend(
+                crd_dirs
+                    .iter()
+                    .map(|dir| dir.join(group).join(format!("{}_{}.json", kind, version))),
+            );
+        }
+
+        // only the first label of the group is part of the file name, e.g. `ingress-networking-v1`
+        let file = match group.split('.').next() {
+            Some(group) if !group.is_empty() => format!("{}-{}-{}.json", kind, group, version),
+            _ => format!("{}-{}.json", kind, version),
+        };
+        candidates.push(
+            self.schema_dir
+                .join(format!(
+                    "{}-standalone-strict",
+                    normalize_version(kube_version)
+                ))
+                .join(file),
+        );
+
+        candidates
+    }
+
+    /// Read and compile the given schema unless that happened before; returns whether it exists
+    fn load(&self, file: &Path) -> anyhow::Result<bool> {
+        if let Some(schema) = self.schemas.borrow().get(file) {
+            return Ok(schema.is_some());
+        }
+
+        let schema = if file.exists() {
+            debug!("compiling schema {:?}", file);
+            let content = std::fs::read_to_string(file)
+                .with_context(|| format!("can not read schema {:?}", file))?;
+            let json: serde_json::Value = serde_json::from_str(&content)
+                .with_context(|| format!("schema {:?} is not valid JSON", file))?;
+            match JSONSchema::compile(&json) {
+                Ok(schema) => Some(schema),
+                Err(err) => bail!("can not compile schema {:?}: {}", file, err),
+            }
+        } else {
+            None
+        };
+
+        let exists = schema.is_some();
+        self.schemas.borrow_mut().insert(file.to_path_buf(), schema);
+
+        Ok(exists)
+    }
+
+    /// Validate a single document against a schema which was loaded before
+    fn check(&self, file: &Path, document: &Value) -> anyhow::Result<Vec<String>> {
+        let instance = serde_json::to_value(document)?;
+        let schemas = self.schemas.borrow();
+        let schema = match schemas.get(file) {
+            Some(Some(schema)) => schema,
+            _ => bail!("schema {:?} was not loaded", file),
+        };
+
+        let problems = match schema.validate(&instance) {
+            Ok(_) => vec![],
+            Err(errors) => errors
+                .map(|err| {
+                    let path = err.instance_path.to_string();
+                    if path.is_empty() {
+                        err.to_string()
+                    } else {
+                        format!("{}: {}", path, err)
+                    }
+                })
+                .collect(),
+        };
+
+        Ok(problems)
+    }
+}
+
+/// Turn a version like `1.29` into the directory prefix used for schemas, e.g. `v1.29.0`
+fn normalize_version(version: &str) -> String {
+    if version == "master" {
+        return version.to_string();
+    }
+
+    let version = version.trim_start_matches('v');
+    match version.split('.').count() {
+        2 => format!("v{}.0", version),
+        _ => format!("v{}", version),
+    }
+}
+
+/// Default location of locally cached schemas: `$XDG_CACHE_HOME/helm-templexer/schemas`, falling
+/// back to `~/.cache/helm-templexer/schemas`
+pub fn default_schema_dir() -> PathBuf {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(|| PathBuf::from(".cache"));
+
+    cache.join("helm-templexer").join("schemas")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempDir;
+    use pretty_assertions::assert_eq;
+
+    const SCHEMA: &str = r#"{
+  "type": "object",
+  "required": ["apiVersion", "kind"],
+  "properties": {
+    "apiVersion": { "type": "string" },
+    "kind": { "type": "string" },
+    "metadata": { "type": "object" },
+    "spec": {
+      "type": "object",
+      "properties": { "replicas": { "type": "integer" } },
+      "additionalProperties": false
+    }
+  }
+}"#;
+
+    #[test]
+    fn versions_are_normalized() {
+        assert_eq!("v1.29.0", normalize_version("1.29"));
+        assert_eq!("v1.29.3", normalize_version("v1.29.3"));
+        assert_eq!("master", normalize_version("master"));
+    }
+
+    #[test]
+    fn crd_schemas_take_precedence() {
+        let validator = SchemaValidator::new(PathBuf::from("schemas"), false);
+
+        assert_eq!(
+            vec![
+                PathBuf::from("crds/monitoring.coreos.com/servicemonitor_v1.json"),
+                PathBuf::from(
+                    "schemas/v1.29.0-standalone-strict/servicemonitor-monitoring-v1.json"
+                ),
+            ],
+            validator.candidates(
+                "monitoring.coreos.com/v1",
+                "ServiceMonitor",
+                "1.29",
+                &[PathBuf::from("crds")]
+            )
+        );
+        assert_eq!(
+            vec![PathBuf::from(
+                "schemas/v1.29.0-standalone-strict/service-v1.json"
+            )],
+            validator.candidates("v1", "Service", "1.29", &[PathBuf::from("crds")])
+        );
+    }
+
+    #[test]
+    fn problems_are_reported_per_resource() {
+        let temp = TempDir::new("schema").unwrap();
+        let dir = temp.path().to_path_buf();
+        temp.write("v1.29.0-standalone-strict/deployment-apps-v1.json", SCHEMA)
+            .unwrap();
+
+        let manifest = r#"---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: good
+spec:
+  replicas: 2
+---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: bad
+spec:
+  replica: 2
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: unknown
+"#;
+
+        let problems = SchemaValidator::new(dir.clone(), false)
+            .validate(manifest, "1.29", &[])
+            .unwrap();
+        let ignored = SchemaValidator::new(dir, true)
+            .validate(manifest, "1.29", &[])
+            .unwrap();
+
+        assert_eq!(2, problems.len());
+        assert!(problems[0].starts_with("Deployment bad (apps/v1): /spec: "));
+        assert!(problems[0].contains("'replica' was unexpected"));
+        assert!(problems[1].starts_with("Service unknown (v1): no schema found"));
+        assert_eq!(1, ignored.len());
+    }
+}
@@ -0,0 +1,434 @@
+use crate::manifest;
+use regex::Regex;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+
+/// Built-in post-processing of rendered manifests, configured per config file and deployment
+///
+/// Transforms run in-process on the output of `helm template` (and any pipe commands) before the
+/// manifest is written. Documents which are modified by a transform are re-formatted, which also
+/// drops the comments they contain; all other documents are kept as they are.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Transforms {
+    /// Label keys to remove from `metadata.labels` of every resource
+    pub remove_labels: Option<Vec<String>>,
+
+    /// Labels to set in `metadata.labels` of every resource
+    pub add_labels: Option<BTreeMap<String, String>>,
+
+    /// Annotation keys to remove from `metadata.annotations` of every resource
+    pub remove_annotations: Option<Vec<String>>,
+
+    /// Annotations to set in `metadata.annotations` of every resource
+    pub add_annotations: Option<BTreeMap<String, String>>,
+
+    /// Remove comment lines, e.g. the `# Source:` comments added by helm
+    pub drop_comments: Option<bool>,
+
+    /// Sort documents by kind, namespace and name
+    pub sort: Option<bool>,
+
+    /// Remove documents without any content
+    pub remove_empty: Option<bool>,
+
+    /// Re-format every document consistently
+    pub normalize: Option<bool>,
+}
+
+/// A single document of a manifest, along with its parsed contents
+struct Document {
+    text: String,
+    value: Value,
+}
+
+impl Transforms {
+    /// Combine the transforms of a config with the ones of a deployment
+    /// Lists are appended, labels and annotations set by the deployment take precedence, and
+    /// switches of the deployment override the ones of the config.
+    pub fn merge(&self, other: &Transforms) -> Transforms {
+        fn list(a: &Option<Vec<String>>, b: &Option<Vec<String>>) -> Option<Vec<String>> {
+            match (a, b) {
+                (None, None) => None,
+                _ => Some(a.iter().chain(b.iter()).flatten().cloned().collect()),
+            }
+        }
+
+        fn map(
+            a: &Option<BTreeMap<String, String>>,
+            b: &Option<BTreeMap<String, String>>,
+        ) -> Option<BTreeMap<String, String>> {
+            match (a, b) {
+                (None, None) => None,
+                _ => Some(
+                    a.iter()
+                        .chain(b.iter())
+                        .flatten()
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect(),
+                ),
+            }
+        }
+
+        Transforms {
+            remove_labels: list(&self.remove_labels, &other.remove_labels),
+            add_labels: map(&self.add_labels, &other.add_labels),
+            remove_annotations: list(&self.remove_annotations, &other.remove_annotations),
+            add_annotations: map(&self.add_annotations, &other.add_annotations),
+            drop_comments: other.drop_comments.or(self.drop_comments),
+            sort: other.sort.or(self.sort),
+            remove_empty: other.remove_empty.or(self.remove_empty),
+            normalize: other.normalize.or(self.normalize),
+        }
+    }
+
+    /// Whether applying the transforms would leave any manifest untouched
+    pub fn is_empty(&self) -> bool {
+        self.remove_labels.iter().flatten().next().is_none()
+            && self.add_labels.iter().flatten().next().is_none()
+            && self.remove_annotations.iter().flatten().next().is_none()
+            && self.add_annotations.iter().flatten().next().is_none()
+            && !self.drop_comments.unwrap_or(false)
+            && !self.sort.unwrap_or(false)
+            && !self.remove_empty.unwrap_or(false)
+            && !self.normalize.unwrap_or(false)
+    }
+
+    /// Apply all transforms to the given multi-document manifest
+    pub fn apply(&self, manifest: &str) -> anyhow::Result<String> {
+        let mut documents = split(manifest)
+            .into_iter()
+            .map(|text| {
+                // documents which only contain comments have no value
+                let value = manifest::documents(&text)?
+                    .into_iter()
+                    .next()
+                    .unwrap_or(Value::Null);
+                Ok(Document { text, value })
+            })
+            .collect::<anyhow::Result<Vec<Document>>>()?;
+
+        if self.remove_empty.unwrap_or(false) {
+            documents.retain(|d| !d.value.is_null());
+        }
+
+        for document in &mut documents {
+            let mut changed = false;
+            changed |= edit(
+                &mut document.value,
+                "labels",
+                &self.remove_labels,
+                &self.add_labels,
+            );
+            changed |= edit(
+                &mut document.value,
+                "annotations",
+                &self.remove_annotations,
+                &self.add_annotations,
+            );
+
+            if changed || (self.normalize.unwrap_or(false) && !document.value.is_null()) {
+                let text = serde_yaml::to_string(&document.value)?;
+                document.text = text.trim_start_matches("---\n").to_string();
+            }
+
+            if self.drop_comments.unwrap_or(false) {
+                document.text = drop_comments(&document.text);
+            }
+        }
+
+        if self.sort.unwrap_or(false) {
+            documents.sort_by_cached_key(|d| {
+                let field = |path: &[&str]| {
+                    path.iter()
+                        .fold(&d.value, |v, key| &v[*key])
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                (
+                    field(&["kind"]),
+                    field(&["metadata", "namespace"]),
+                    field(&["metadata", "name"]),
+                )
+            });
+        }
+
+        let mut output = String::new();
+        for document in documents {
+            output.push_str("---\n");
+            output.push_str(&document.text);
+            if !document.text.is_empty() && !document.text.ends_with('\n') {
+                output.push('\n');
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Split a manifest into documents at `---` markers; the markers themselves are dropped
+/// Content before the first marker only counts as a document if it is not blank.
+fn split(manifest: &str) -> Vec<String> {
+    let mut documents = vec![];
+    let mut current = String::new();
+    let mut started = false;
+
+    for line in manifest.lines() {
+        if line == "---" || line.starts_with("--- ") {
+            if started || !current.trim().is_empty() {
+                documents.push(current);
+            }
+            current = String::new();
+            started = true;
+            continue;
+        }
+
+        current.push_str(line);
+        current.push('\n');
+    }
+
+    if started || !current.trim().is_empty() {
+        documents.push(current);
+    }
+
+    documents
+}
+
+/// Remove and add entries of `metadata.<field>`; returns whether the document was changed
+fn edit(
+    document: &mut Value,
+    field: &str,
+    remove: &Option<Vec<String>>,
+    add: &Option<BTreeMap<String, String>>,
+) -> bool {
+    let metadata = match document.get_mut("metadata") {
+        Some(Value::Mapping(metadata)) => metadata,
+        _ => return false,
+    };
+    let mut changed = false;
+    let key = Value::from(field);
+
+    if let Some(Value::Mapping(entries)) = metadata.get_mut(&key) {
+        for name in remove.iter().flatten() {
+            changed |= entries.remove(&Value::from(name.as_str())).is_some();
+        }
+    }
+
+    let add = match add {
+        Some(add) if !add.is_empty() => add,
+        _ => return changed,
+    };
+
+    if !matches!(metadata.get(&key), Some(Value::Mapping(_))) {
+        metadata.insert(key.clone(), Value::Mapping(Mapping::new()));
+    }
+    if let Some(Value::Mapping(entries)) = metadata.get_mut(&key) {
+        for (name, value) in add {
+            let value = Value::from(value.as_str());
+            let name = Value::from(name.as_str());
+            if entries.get(&name) != Some(&value) {
+                entries.insert(name, value);
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
+
+/// Remove comment lines from a document, leaving the contents of block scalars untouched
+/// Comments following a value on the same line are kept, as they can not be told apart from
+/// content without parsing.
+fn drop_comments(document: &str) -> String {
+    let block_scalar = Regex::new(r"(^|[:-])\s*[|>][-+0-9]*\s*$").expect("valid regex");
+    let mut block_indent: Option<usize> = None;
+    let mut output = String::new();
+
+    for line in document.lines() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if let Some(parent) = block_indent {
+            if trimmed.is_empty() || indent > parent {
+                output.push_str(line);
+                output.push('\n');
+                continue;
+            }
+            block_indent = None;
+        }
+
+        if trimmed.starts_with('#') {
+            continue;
+        }
+
+        if block_scalar.is_match(line) {
+            block_indent = Some(indent);
+        }
+
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const MANIFEST: &str = r#"---
+# Source: my-app/templates/service.yaml
+apiVersion: v1
+kind: Service
+metadata:
+  name: my-app
+  labels:
+    helm.sh/chart: my-app-0.1.0
+    app: my-app
+---
+# Source: my-app/templates/empty.yaml
+---
+# Source: my-app/templates/configmap.yaml
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: my-app
+data:
+  script.sh: |
+    # keep me
+    echo hello
+"#;
+
+    #[test]
+    fn empty_transforms_are_detected() {
+        assert!(Transforms::default().is_empty());
+        assert!(Transforms {
+            sort: Some(false),
+            remove_labels: Some(vec![]),
+            ..Default::default()
+        }
+        .is_empty());
+        assert!(!Transforms {
+            sort: Some(true),
+            ..Default::default()
+        }
+        .is_empty());
+    }
+
+    #[test]
+    fn deployment_transforms_take_precedence() {
+        let config = Transforms {
+            remove_labels: Some(vec!["helm.sh/chart".to_string()]),
+            add_labels: Some(BTreeMap::from([
+                ("team".to_string(), "platform".to_string()),
+                ("tier".to_string(), "backend".to_string()),
+            ])),
+            sort: Some(true),
+            drop_comments: Some(true),
+            ..Default::default()
+        };
+        let deployment = Transforms {
+            remove_labels: Some(vec!["chart".to_string()]),
+            add_labels: Some(BTreeMap::from([("tier".to_string(), "edge".to_string())])),
+            sort: Some(false),
+            ..Default::default()
+        };
+
+        let merged = config.merge(&deployment);
+
+        assert_eq!(
+            Some(vec!["helm.sh/chart".to_string(), "chart".to_string()]),
+            merged.remove_labels
+        );
+        assert_eq!(
+            Some(BTreeMap::from([
+                ("team".to_string(), "platform".to_string()),
+                ("tier".to_string(), "edge".to_string()),
+            ])),
+            merged.add_labels
+        );
+        assert_eq!(Some(false), merged.sort);
+        assert_eq!(Some(true), merged.drop_comments);
+    }
+
+    #[test]
+    fn comments_and_empty_documents_are_dropped() {
+        let transforms = Transforms {
+            drop_comments: Some(true),
+            remove_empty: Some(true),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            r#"---
+apiVersion: v1
+kind: Service
+metadata:
+  name: my-app
+  labels:
+    helm.sh/chart: my-app-0.1.0
+    app: my-app
+---
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: my-app
+data:
+  script.sh: |
+    # keep me
+    echo hello
+"#,
+            transforms.apply(MANIFEST).unwrap()
+        );
+    }
+
+    #[test]
+    fn labels_are_edited_and_documents_sorted() {
+        let transforms = Transforms {
+            remove_labels: Some(vec!["helm.sh/chart".to_string()]),
+            add_annotations: Some(BTreeMap::from([(
+                "owner".to_string(),
+                "platform".to_string(),
+            )])),
+            sort: Some(true),
+            remove_empty: Some(true),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            r##"---
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: my-app
+  annotations:
+    owner: platform
+data:
+  script.sh: "# keep me\necho hello\n"
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: my-app
+  labels:
+    app: my-app
+  annotations:
+    owner: platform
+"##,
+            transforms.apply(MANIFEST).unwrap()
+        );
+    }
+
+    #[test]
+    fn untouched_documents_keep_their_formatting() {
+        let transforms = Transforms {
+            remove_labels: Some(vec!["does-not-exist".to_string()]),
+            ..Default::default()
+        };
+
+        assert_eq!(MANIFEST, transforms.apply(MANIFEST).unwrap());
+    }
+}
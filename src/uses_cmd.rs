@@ -0,0 +1,272 @@
+use crate::config::Config;
+use crate::{OutputFormat, UsesCmdOpts};
+use anyhow::Context;
+use log::info;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// The uses sub command answers which deployments consume a given values file or chart.
+pub struct UsesCmd {
+    opts: UsesCmdOpts,
+}
+
+/// A single deployment which references the requested path
+#[derive(Serialize, Debug, PartialEq)]
+struct Usage {
+    /// Configuration file the deployment is defined in, as passed to the templexer
+    config: PathBuf,
+
+    /// Name of the deployment
+    deployment: String,
+
+    /// Whether the deployment would be rendered
+    enabled: bool,
+
+    /// How the path is referenced: `values` or `chart`
+    reference: &'static str,
+
+    /// The path as written in the configuration file
+    path: PathBuf,
+}
+
+impl UsesCmd {
+    /// Create sub command struct to look up the usages of the given path
+    pub fn new(opts: UsesCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to run the lookup
+    /// will print all deployments that reference the given path
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("uses options: {:?}", self.opts);
+
+        let target = self.opts.path.canonicalize().with_context(|| {
+            format!(
+                "File {:?} does not exist or is not readable",
+                self.opts.path
+            )
+        })?;
+
+        let mut usages = vec![];
+        for file in &self.opts.input_files {
+            let cfg = Config::load(file)?;
+            cfg.switch_working_directory(file)?;
+            let found = Self::find_usages(&cfg, &target);
+            cfg.reset_working_directory()?;
+
+            usages.extend(found?);
+        }
+
+        if usages.is_empty() {
+            info!("no deployment references {:?}", self.opts.path);
+        }
+
+        match self.opts.format {
+            OutputFormat::Text => {
+                for u in &usages {
+                    let disabled = if u.enabled { "" } else { " (disabled)" };
+                    println!(
+                        "{}: {}{} ({}: {})",
+                        u.config.display(),
+                        u.deployment,
+                        disabled,
+                        u.reference,
+                        u.path.display()
+                    );
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&usages)?),
+        }
+
+        Ok(())
+    }
+
+    /// Collect all deployments of the given config which reference `target`, either directly
+    /// as a values file or through the chart directory.
+    /// Make sure to switch the working directory to the config file before calling this.
+    fn find_usages(cfg: &Config, target: &Path) -> anyhow::Result<Vec<Usage>> {
+        let mut usages = vec![];
+
+        let chart = cfg
+            .chart
+            .canonicalize()
+            .with_context(|| format!("Chart {:?} does not exist or is not readable", cfg.chart))?;
+        let config_enabled = cfg.enabled.unwrap_or(true);
+
+        // value files may live inside the chart directory; those are not considered part of the chart
+        let values = cfg.deployments.iter().filter_map(|d| d.values.as_ref());
+        let is_values_file = cfg
+            .values
+            .iter()
+            .chain(values)
+            .flatten()
+            .any(|v| matches!(v.canonicalize(), Ok(v) if v == target));
+
+        for d in &cfg.deployments {
+            let enabled = config_enabled && d.enabled.unwrap_or(true);
+            let values = cfg.values.iter().chain(d.values.iter()).flatten();
+
+            let mut referenced_as_values = false;
+            for v in values {
+                // disabled deployments are not validated and may reference files which do not exist
+                let matches = match v.canonicalize() {
+                    Ok(v) => v.starts_with(target),
+                    Err(_) => false,
+                };
+
+                if matches {
+                    referenced_as_values = true;
+                    usages.push(Usage {
+                        config: cfg.config_file.clone(),
+                        deployment: d.name.clone(),
+                        enabled,
+                        reference: "values",
+                        path: v.clone(),
+                    });
+                }
+            }
+
+            let references_chart = target.starts_with(&chart) || chart.starts_with(target);
+            if !referenced_as_values && !is_values_file && references_chart {
+                usages.push(Usage {
+                    config: cfg.config_file.clone(),
+                    deployment: d.name.clone(),
+                    enabled,
+                    reference: "chart",
+                    path: cfg.chart.clone(),
+                });
+            }
+        }
+
+        Ok(usages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Deployment;
+    use pretty_assertions::assert_eq;
+
+    fn get_config() -> Config {
+        Config {
+            version: "v2".to_string(),
+            enabled: Some(true),
+            helm: None,
+            chart: PathBuf::from("tests/data/nginx-chart"),
+            namespace: None,
+            release_name: "my-app".to_string(),
+            output_path: PathBuf::from("manifests"),
+            additional_options: None,
+            values: Some(vec![PathBuf::from(
+                "tests/data/nginx-chart/values/default.yaml",
+            )]),
+            pipe: None,
+            timeout: None,
+            transforms: None,
+            kube_version: None,
+            crd_schemas: None,
+            policies: None,
+            deployments: vec![],
+            original_working_directory: Default::default(),
+            config_file: PathBuf::from("my-app.yaml"),
+        }
+    }
+
+    fn get_deployment(name: &str, values: &str) -> Deployment {
+        Deployment {
+            name: name.to_string(),
+            enabled: None,
+            release_name: None,
+            additional_options: None,
+            values: Some(vec![PathBuf::from(values)]),
+            pipe: None,
+            timeout: None,
+            transforms: None,
+            kube_version: None,
+            policy_exceptions: None,
+        }
+    }
+
+    fn canonical(path: &str) -> PathBuf {
+        PathBuf::from(path).canonicalize().unwrap()
+    }
+
+    #[test]
+    fn values_file_is_found_for_its_deployments_only() {
+        let mut cfg = get_config();
+        cfg.deployments = vec![
+            get_deployment("edge", "tests/data/nginx-chart/values/edge.yaml"),
+            get_deployment("prod", "tests/data/nginx-chart/values/prod.yaml"),
+        ];
+
+        let got = UsesCmd::find_usages(&cfg, &canonical("tests/data/nginx-chart/values/prod.yaml"))
+            .unwrap();
+
+        assert_eq!(
+            vec![Usage {
+                config: PathBuf::from("my-app.yaml"),
+                deployment: "prod".to_string(),
+                enabled: true,
+                reference: "values",
+                path: PathBuf::from("tests/data/nginx-chart/values/prod.yaml"),
+            }],
+            got
+        );
+    }
+
+    #[test]
+    fn top_level_values_file_is_found_for_all_deployments() {
+        let mut cfg = get_config();
+        let mut prod = get_deployment("prod", "tests/data/nginx-chart/values/prod.yaml");
+        prod.enabled = Some(false);
+        cfg.deployments = vec![
+            get_deployment("edge", "tests/data/nginx-chart/values/edge.yaml"),
+            prod,
+        ];
+
+        let got = UsesCmd::find_usages(
+            &cfg,
+            &canonical("tests/data/nginx-chart/values/default.yaml"),
+        )
+        .unwrap();
+
+        let got: Vec<(&str, bool)> = got
+            .iter()
+            .map(|u| (u.deployment.as_str(), u.enabled))
+            .collect();
+        assert_eq!(vec![("edge", true), ("prod", false)], got);
+    }
+
+    #[test]
+    fn chart_files_are_found_through_the_chart_directory() {
+        let mut cfg = get_config();
+        cfg.deployments = vec![get_deployment(
+            "edge",
+            "tests/data/nginx-chart/values/edge.yaml",
+        )];
+
+        let got = UsesCmd::find_usages(
+            &cfg,
+            &canonical("tests/data/nginx-chart/templates/deployment.yaml"),
+        )
+        .unwrap();
+
+        assert_eq!(1, got.len());
+        assert_eq!("chart", got[0].reference);
+        assert_eq!(PathBuf::from("tests/data/nginx-chart"), got[0].path);
+    }
+
+    #[test]
+    fn unrelated_files_are_not_found() {
+        let mut cfg = get_config();
+        cfg.deployments = vec![get_deployment(
+            "edge",
+            "tests/data/nginx-chart/values/edge.yaml",
+        )];
+
+        let got = UsesCmd::find_usages(&cfg, &canonical("README.md")).unwrap();
+
+        assert!(got.is_empty());
+    }
+}
@@ -1,5 +1,10 @@
-use crate::config::{Config, ValidationOpts};
-use crate::ValidateCmdOpts;
+use crate::config::Config;
+use crate::diagnostic::{self, Diagnostic};
+use crate::junit::{JUnitReport, Outcome, TestCase, TestSuite};
+use crate::process;
+use crate::{DiagnosticFormat, ValidateCmdOpts};
+use std::path::Path;
+use std::time::Instant;
 
 /// The validate sub command allows for checking any given configuration file without
 /// rendering to disk.
@@ -18,18 +23,113 @@ impl ValidateCmd {
     pub fn run(&self) -> anyhow::Result<()> {
         log::debug!("validation options: {:?}", self.opts);
 
+        let mut junit = JUnitReport::default();
+        let mut diagnostics = vec![];
+        let result = self.validate_files(&mut junit, &mut diagnostics);
+
+        if let Some(file) = &self.opts.junit {
+            junit.write(file)?;
+            log::info!("JUnit report written to {:?}", file);
+        }
+
+        if let Some(file) = &self.opts.sarif {
+            let log = serde_json::to_string_pretty(&diagnostic::sarif(&diagnostics))?;
+            process::write_atomically(file, log.as_bytes())?;
+            log::info!("SARIF log written to {:?}", file);
+        }
+
+        match self.opts.format {
+            DiagnosticFormat::Text => {}
+            DiagnosticFormat::Github => {
+                for diagnostic in &diagnostics {
+                    println!("{}", diagnostic.to_github());
+                }
+            }
+            DiagnosticFormat::Sarif if self.opts.sarif.is_some() => {}
+            DiagnosticFormat::Sarif => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&diagnostic::sarif(&diagnostics))?
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Validate all input files, recording every check as a test case and every failure as diagnostic
+    /// Stops at the first invalid file when printing plain text without a JUnit report or SARIF log;
+    /// otherwise all files are validated and the first failure is returned at the end.
+    fn validate_files(
+        &self,
+        junit: &mut JUnitReport,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> anyhow::Result<()> {
+        let mut first_failure = None;
+
         for file in &self.opts.input_files {
-            let opts = ValidationOpts {
-                config_file: Some(file.clone()),
-                ..Default::default()
-            };
+            let mut suite = TestSuite::new(file);
+            let errors = Self::run_checks(file, &mut suite)?;
+            junit.suites.push(suite);
+            diagnostics.extend(errors.iter().map(|e| Diagnostic::from_error(file, e)));
 
-            Config::load(file)?
-                .switch_working_directory(file)?
-                .validate(&opts)?
-                .reset_working_directory()?;
+            if let Some(err) = errors.into_iter().next() {
+                if self.opts.format == DiagnosticFormat::Text
+                    && self.opts.junit.is_none()
+                    && self.opts.sarif.is_none()
+                {
+                    return Err(err);
+                }
+                first_failure.get_or_insert(err);
+            }
+        }
+
+        match first_failure {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Run every check of the given file individually and add it to the suite
+    /// All checks run even if one fails; returns the errors of all failed checks.
+    fn run_checks(file: &Path, suite: &mut TestSuite) -> anyhow::Result<Vec<anyhow::Error>> {
+        let start = Instant::now();
+        let cfg = match Config::load(file) {
+            Ok(cfg) => cfg,
+            Err(err) => {
+                suite.cases.push(TestCase {
+                    name: "load".to_string(),
+                    seconds: start.elapsed().as_secs_f64(),
+                    outcome: Outcome::Failed(format!("{:#}", err)),
+                });
+                return Ok(vec![err]);
+            }
+        };
+        suite.cases.push(TestCase {
+            name: "load".to_string(),
+            seconds: start.elapsed().as_secs_f64(),
+            outcome: Outcome::Passed,
+        });
+
+        cfg.switch_working_directory(file)?;
+        let mut errors = vec![];
+        for (name, result) in cfg.checks() {
+            let outcome = match result {
+                Ok(()) => Outcome::Passed,
+                Err(err) => {
+                    let outcome = Outcome::Failed(format!("{:#}", err));
+                    errors.push(err);
+                    outcome
+                }
+            };
+            suite.cases.push(TestCase {
+                name: name.to_string(),
+                seconds: 0.0,
+                outcome,
+            });
         }
+        cfg.reset_working_directory()?;
 
-        Ok(())
+        Ok(errors)
     }
 }
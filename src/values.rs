@@ -0,0 +1,599 @@
+use crate::config::{Config, Deployment};
+use anyhow::{anyhow, bail, Context};
+use log::warn;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Path of a value within the merged values, e.g. `["image", "tag"]`
+pub type KeyPath = Vec<String>;
+
+/// Values of a deployment as `helm template` sees them, along with the source that set each value
+#[derive(Debug, Default)]
+pub struct MergedValues {
+    pub values: Value,
+
+    /// Source which last set each value: a values file or a `--set` option
+    /// Only values which are not merged further are recorded, i.e. scalars, lists and empty mappings.
+    pub sources: BTreeMap<KeyPath, String>,
+}
+
+/// Options of `helm template` setting values, which are merged
+const SUPPORTED_FLAGS: [&str; 4] = ["-f", "--values", "--set", "--set-string"];
+
+/// Options of `helm template` setting values, which can not be merged
+const UNSUPPORTED_FLAGS: [&str; 3] = ["--set-file", "--set-json", "--set-literal"];
+
+/// Single layer of values, in order of precedence
+enum Layer {
+    File(String),
+    Set { option: String, assignments: String },
+}
+
+impl MergedValues {
+    /// Load the given config file and merge the values of the named deployment, see `MergedValues::of`
+    pub fn load(
+        file: &Path,
+        deployment: &str,
+        additional_options: &[String],
+    ) -> anyhow::Result<Self> {
+        let cfg = Config::load(file)?;
+        let d = cfg
+            .deployments
+            .iter()
+            .find(|d| d.name == deployment)
+            .ok_or_else(|| anyhow!("{}: no deployment named {:?}", file.display(), deployment))?;
+
+        cfg.switch_working_directory(file)?;
+        let merged = Self::of(&cfg, d, additional_options);
+        cfg.reset_working_directory()?;
+
+        merged
+    }
+
+    /// Merge the values of the chart, the value files and `--set` options of the given deployment
+    /// Follows the precedence of helm: the chart's `values.yaml`, then all value files in order,
+    /// then all `--set` and then all `--set-string` options, whether they are part of the config
+    /// or given as additional options. Make sure to switch the working
+    /// directory to the config file before calling this.
+    pub fn of(
+        cfg: &Config,
+        deployment: &Deployment,
+        additional_options: &[String],
+    ) -> anyhow::Result<Self> {
+        let mut merged = Self {
+            values: Value::Mapping(Mapping::new()),
+            ..Default::default()
+        };
+
+        for layer in layers(cfg, deployment, additional_options) {
+            match layer {
+                Layer::File(file) => {
+                    let content = std::fs::read_to_string(&file)
+                        .with_context(|| format!("can not read values file {:?}", file))?;
+                    let values: Value = serde_yaml::from_str(&content)
+                        .with_context(|| format!("can not parse values file {:?}", file))?;
+                    // empty files contain no values at all
+                    if !values.is_null() {
+                        merged.merge(&values, &file);
+                    }
+                }
+                Layer::Set {
+                    option,
+                    assignments,
+                } => {
+                    let source = format!("{} {}", option, assignments);
+                    for (path, value) in parse_set(&assignments, option == "--set-string")? {
+                        merged.set(&path, value, &source)?;
+                    }
+                }
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Deep-merge the given values into the merged ones; `null` removes a value
+    pub fn merge(&mut self, overlay: &Value, source: &str) {
+        merge(
+            &mut self.values,
+            overlay,
+            &mut vec![],
+            source,
+            &mut self.sources,
+        );
+    }
+
+    /// Set a single value, creating mappings along the path
+    fn set(&mut self, path: &[Segment], value: Value, source: &str) -> anyhow::Result<()> {
+        // build a document containing only the value, then merge it like a values file
+        let mut overlay = value;
+        for segment in path.iter().rev() {
+            overlay = match segment {
+                Segment::Key(key) => {
+                    let mut mapping = Mapping::new();
+                    mapping.insert(Value::from(key.as_str()), overlay);
+                    Value::Mapping(mapping)
+                }
+                Segment::Index(_) => bail!(
+                    "list indexes are not supported in {:?}; set the whole list instead",
+                    source
+                ),
+            };
+        }
+
+        self.merge(&overlay, source);
+        Ok(())
+    }
+
+    /// Format the values as YAML, adding the source of each value as comment
+    pub fn to_annotated_yaml(&self) -> anyhow::Result<String> {
+        let mut out = String::new();
+        annotate(&self.values, &mut vec![], 0, &self.sources, &mut out)?;
+        Ok(out)
+    }
+}
+
+/// Collect the layers of values of the given deployment, in order of precedence
+fn layers(cfg: &Config, deployment: &Deployment, additional_options: &[String]) -> Vec<Layer> {
+    let mut files = vec![];
+    let mut sets = vec![];
+    let mut set_strings = vec![];
+
+    if cfg.chart.is_dir() {
+        let defaults = cfg.chart.join("values.yaml");
+        if defaults.exists() {
+            files.push(defaults.to_string_lossy().to_string());
+        }
+    } else {
+        warn!(
+            "chart {:?} is not a directory; its default values are not included",
+            cfg.chart
+        );
+    }
+
+    // options in the order they are passed to `helm template`, see `RenderCmd::plan`
+    let values = |files: &Option<Vec<PathBuf>>| -> Vec<String> {
+        files
+            .iter()
+            .flatten()
+            .map(|f| format!("--values={}", f.to_string_lossy()))
+            .collect()
+    };
+    let mut options = values(&cfg.values);
+    options.extend(cfg.additional_options.iter().flatten().cloned());
+    options.extend(additional_options.iter().cloned());
+    options.extend(values(&deployment.values));
+    options.extend(deployment.additional_options.iter().flatten().cloned());
+
+    let tokens: Vec<&str> = options.iter().flat_map(|o| o.split_whitespace()).collect();
+    let mut i = 0;
+    while i < tokens.len() {
+        let (flag, inline) = match tokens[i].split_once('=') {
+            Some((flag, value)) if flag.starts_with('-') => (flag, Some(value.to_string())),
+            _ => (tokens[i], None),
+        };
+        i += 1;
+
+        if !SUPPORTED_FLAGS.contains(&flag) && !UNSUPPORTED_FLAGS.contains(&flag) {
+            continue;
+        }
+        let value = match inline {
+            Some(value) => value,
+            None if i < tokens.len() => {
+                i += 1;
+                tokens[i - 1].to_string()
+            }
+            None => continue,
+        };
+
+        match flag {
+            "-f" | "--values" => files.push(value),
+            "--set" => sets.push(value),
+            "--set-string" => set_strings.push(value),
+            _ => warn!(
+                "{} {} is not supported; its values are not included",
+                flag, value
+            ),
+        }
+    }
+
+    let set = |option: &str, assignments: Vec<String>| {
+        assignments.into_iter().map({
+            let option = option.to_string();
+            move |assignments| Layer::Set {
+                option: option.clone(),
+                assignments,
+            }
+        })
+    };
+
+    files
+        .into_iter()
+        .map(Layer::File)
+        .chain(set("--set", sets))
+        .chain(set("--set-string", set_strings))
+        .collect()
+}
+
+/// Step of a key path of a `--set` assignment
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Parse the assignments of a `--set` option, e.g. `image.tag=1.2.3,replicas=3`
+/// Commas and dots can be escaped with a backslash; `{a,b}` sets a list. Unless `as_string` is
+/// set, `true`, `false`, `null` and integers are converted like helm does.
+fn parse_set(assignments: &str, as_string: bool) -> anyhow::Result<Vec<(Vec<Segment>, Value)>> {
+    let mut parsed = vec![];
+
+    for assignment in split_unescaped(assignments, ',', true) {
+        let (key, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid --set assignment {:?}: missing '='", assignment))?;
+
+        let mut path = vec![];
+        for part in split_unescaped(key, '.', false) {
+            let part = part.replace("\\.", ".");
+            match part.find('[') {
+                Some(start) if part.ends_with(']') => {
+                    path.push(Segment::Key(part[..start].to_string()));
+                    let index = part[start + 1..part.len() - 1].parse().map_err(|_| {
+                        anyhow!("invalid --set assignment {:?}: bad list index", assignment)
+                    })?;
+                    path.push(Segment::Index(index));
+                }
+                _ => path.push(Segment::Key(part)),
+            }
+        }
+
+        let value = value.replace("\\,", ",");
+        let value = match value.strip_prefix('{').and_then(|v| v.strip_suffix('}')) {
+            Some(items) => Value::Sequence(
+                items
+                    .split(',')
+                    .filter(|i| !i.is_empty())
+                    .map(|i| typed(i, as_string))
+                    .collect(),
+            ),
+            None => typed(&value, as_string),
+        };
+
+        parsed.push((path, value));
+    }
+
+    Ok(parsed)
+}
+
+/// Split at the separator, unless it is escaped by a backslash or, if `braces` is set, in `{}`
+fn split_unescaped(s: &str, separator: char, braces: bool) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut escaped = false;
+
+    for c in s.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' if braces => depth += 1,
+            '}' if braces && depth > 0 => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(current);
+
+    parts
+}
+
+/// Convert a `--set` value to the type helm would use
+fn typed(value: &str, as_string: bool) -> Value {
+    if as_string {
+        return Value::from(value);
+    }
+
+    match value {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "null" => Value::Null,
+        _ => match value.parse::<i64>() {
+            // helm keeps leading zeros, e.g. in `007`
+            Ok(number) if number.to_string() == value => Value::from(number),
+            _ => Value::from(value),
+        },
+    }
+}
+
+/// Deep-merge `overlay` into `base`, recording the source of each value which is set
+/// Like helm, only `null` removes a key; an empty mapping leaves an existing one as it is.
+fn merge(
+    base: &mut Value,
+    overlay: &Value,
+    path: &mut KeyPath,
+    source: &str,
+    sources: &mut BTreeMap<KeyPath, String>,
+) {
+    let (base_mapping, overlay_mapping) = match (base, overlay) {
+        (Value::Mapping(b), Value::Mapping(o)) => (b, o),
+        (base, overlay) => {
+            *base = overlay.clone();
+            forget(path, sources);
+            record(overlay, path, source, sources);
+            return;
+        }
+    };
+
+    for (key, value) in overlay_mapping {
+        path.push(key_name(key));
+
+        if value.is_null() {
+            base_mapping.remove(key);
+            forget(path, sources);
+        } else if let Some(existing) = base_mapping.get_mut(key) {
+            merge(existing, value, path, source, sources);
+        } else {
+            base_mapping.insert(key.clone(), value.clone());
+            record(value, path, source, sources);
+        }
+
+        path.pop();
+    }
+}
+
+/// Record the source of every value within the given one
+fn record(
+    value: &Value,
+    path: &mut KeyPath,
+    source: &str,
+    sources: &mut BTreeMap<KeyPath, String>,
+) {
+    match value {
+        Value::Mapping(entries) if !entries.is_empty() => {
+            for (key, item) in entries {
+                path.push(key_name(key));
+                record(item, path, source, sources);
+                path.pop();
+            }
+        }
+        _ => {
+            sources.insert(path.clone(), source.to_string());
+        }
+    }
+}
+
+/// Remove the sources of the given value and everything below it
+fn forget(path: &[String], sources: &mut BTreeMap<KeyPath, String>) {
+    sources.retain(|p, _| !p.starts_with(path));
+}
+
+/// Render a key for paths and messages
+pub fn key_name(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        _ => scalar(key),
+    }
+}
+
+/// Format a value as YAML without the document marker, e.g. `"007"` or `[a, b]`
+fn scalar(value: &Value) -> String {
+    serde_yaml::to_string(value)
+        .unwrap_or_default()
+        .trim_start_matches("---")
+        .trim()
+        .to_string()
+}
+
+/// Write values as YAML, adding the source of each value as comment
+fn annotate(
+    value: &Value,
+    path: &mut KeyPath,
+    indent: usize,
+    sources: &BTreeMap<KeyPath, String>,
+    out: &mut String,
+) -> anyhow::Result<()> {
+    let entries = match value {
+        Value::Mapping(entries) => entries,
+        _ => bail!("values must be a mapping"),
+    };
+    let padding = " ".repeat(indent);
+
+    for (key, item) in entries {
+        path.push(key_name(key));
+        let comment = match sources.get(path) {
+            Some(source) => format!("  # {}", source),
+            None => String::new(),
+        };
+
+        match item {
+            Value::Mapping(m) if !m.is_empty() => {
+                out.push_str(&format!("{}{}:\n", padding, scalar(key)));
+                annotate(item, path, indent + 2, sources, out)?;
+            }
+            Value::Sequence(s) if !s.is_empty() => {
+                out.push_str(&format!("{}{}:{}\n", padding, scalar(key), comment));
+                for line in scalar(item).lines() {
+                    out.push_str(&format!("{}  {}\n", padding, line));
+                }
+            }
+            _ => out.push_str(&format!(
+                "{}{}: {}{}\n",
+                padding,
+                scalar(key),
+                scalar(item),
+                comment
+            )),
+        }
+
+        path.pop();
+    }
+
+    Ok(())
+}
+
+/// Print a key path like `image.tag`
+pub fn dotted(path: &[String]) -> String {
+    path.join(".")
+}
+
+/// Flatten values into key paths and the values which are not merged further
+pub fn flatten(values: &Value) -> BTreeMap<KeyPath, Value> {
+    fn walk(value: &Value, path: &mut KeyPath, flat: &mut BTreeMap<KeyPath, Value>) {
+        match value {
+            Value::Mapping(entries) if !entries.is_empty() => {
+                for (key, item) in entries {
+                    path.push(key_name(key));
+                    walk(item, path, flat);
+                    path.pop();
+                }
+            }
+            _ => {
+                flat.insert(path.clone(), value.clone());
+            }
+        }
+    }
+
+    let mut flat = BTreeMap::new();
+    walk(values, &mut vec![], &mut flat);
+    flat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn yaml(s: &str) -> Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn values_are_deep_merged() {
+        let mut merged = MergedValues {
+            values: Value::Mapping(Mapping::new()),
+            ..Default::default()
+        };
+        merged.merge(
+            &yaml("image: {repository: nginx, tag: stable}\nports: [80]\nprobe: {path: /}"),
+            "values.yaml",
+        );
+        merged.merge(
+            &yaml("image: {tag: '1.25'}\nports: [8080, 8443]\nprobe: null"),
+            "prod.yaml",
+        );
+
+        assert_eq!(
+            yaml("image: {repository: nginx, tag: '1.25'}\nports: [8080, 8443]"),
+            merged.values
+        );
+        assert_eq!(
+            "image:\n  repository: nginx  # values.yaml\n  tag: \"1.25\"  # prod.yaml\nports:  # prod.yaml\n  - 8080\n  - 8443\n",
+            merged.to_annotated_yaml().unwrap()
+        );
+    }
+
+    #[test]
+    fn empty_mappings_keep_the_defaults() {
+        let mut merged = MergedValues {
+            values: Value::Mapping(Mapping::new()),
+            ..Default::default()
+        };
+        merged.merge(
+            &yaml(
+                "resources: {limits: {cpu: 100m}}
+labels: {}",
+            ),
+            "values.yaml",
+        );
+        merged.merge(
+            &yaml(
+                "resources: {}
+labels: {}",
+            ),
+            "prod.yaml",
+        );
+
+        assert_eq!(
+            yaml(
+                "resources: {limits: {cpu: 100m}}
+labels: {}"
+            ),
+            merged.values
+        );
+        assert_eq!(
+            "resources:\n  limits:\n    cpu: 100m  # values.yaml\nlabels: {}  # values.yaml\n",
+            merged.to_annotated_yaml().unwrap()
+        );
+    }
+
+    #[test]
+    fn set_options_are_parsed_like_helm() {
+        let parsed = parse_set(
+            r"image.tag=007,replicas=3,enabled=true,hosts={a.example.com,b.example.com},annotations.kubernetes\.io/ingress=x\,y",
+            false,
+        )
+        .unwrap();
+
+        let paths: Vec<String> = parsed
+            .iter()
+            .map(|(path, _)| {
+                path.iter()
+                    .map(|s| match s {
+                        Segment::Key(k) => k.clone(),
+                        Segment::Index(i) => format!("[{}]", i),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("|")
+            })
+            .collect();
+        let values: Vec<&Value> = parsed.iter().map(|(_, v)| v).collect();
+
+        assert_eq!(
+            vec![
+                "image|tag",
+                "replicas",
+                "enabled",
+                "hosts",
+                "annotations|kubernetes.io/ingress"
+            ],
+            paths
+        );
+        assert_eq!(
+            vec![
+                &Value::from("007"),
+                &Value::from(3),
+                &Value::Bool(true),
+                &yaml("[a.example.com, b.example.com]"),
+                &Value::from("x,y"),
+            ],
+            values
+        );
+        assert_eq!(
+            Value::from("3"),
+            parse_set("replicas=3", true).unwrap()[0].1
+        );
+    }
+
+    #[test]
+    fn values_are_flattened_to_key_paths() {
+        let flat = flatten(&yaml("image: {tag: '1.25'}\nports: [80]\nempty: {}"));
+
+        assert_eq!(
+            vec![
+                (vec!["empty".to_string()], yaml("{}")),
+                (
+                    vec!["image".to_string(), "tag".to_string()],
+                    Value::from("1.25")
+                ),
+                (vec!["ports".to_string()], yaml("[80]")),
+            ],
+            flat.into_iter().collect::<Vec<_>>()
+        );
+    }
+}
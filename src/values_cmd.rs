@@ -0,0 +1,34 @@
+use crate::values::MergedValues;
+use crate::ValuesCmdOpts;
+
+/// The values sub command prints the values a deployment is rendered with.
+pub struct ValuesCmd {
+    opts: ValuesCmdOpts,
+}
+
+impl ValuesCmd {
+    /// Create sub command struct to print the values of the given deployment
+    pub fn new(opts: ValuesCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to print the values
+    /// will print the merged values as YAML, optionally annotated with their sources
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("values options: {:?}", self.opts);
+
+        let merged = MergedValues::load(
+            &self.opts.input_file,
+            &self.opts.deployment,
+            self.opts.additional_options.as_deref().unwrap_or_default(),
+        )?;
+
+        if self.opts.show_sources {
+            print!("{}", merged.to_annotated_yaml()?);
+        } else {
+            print!("{}", serde_yaml::to_string(&merged.values)?);
+        }
+
+        Ok(())
+    }
+}
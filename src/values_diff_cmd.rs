@@ -0,0 +1,132 @@
+use crate::diff::{self, Difference};
+use crate::values::{self, MergedValues};
+use crate::{OutputFormat, ValuesDiffCmdOpts};
+use log::info;
+use serde_yaml::Value;
+
+/// The values-diff sub command compares the merged values of two deployments.
+pub struct ValuesDiffCmd {
+    opts: ValuesDiffCmdOpts,
+}
+
+impl ValuesDiffCmd {
+    /// Create sub command struct to compare the values of the given deployments
+    pub fn new(opts: ValuesDiffCmdOpts) -> Self {
+        Self { opts }
+    }
+
+    /// Main entry point to compare the values
+    /// will print every value which was added, removed or changed from the first to the second deployment
+    pub fn run(&self) -> anyhow::Result<()> {
+        log::debug!("values-diff options: {:?}", self.opts);
+
+        let additional_options = self.opts.additional_options.as_deref().unwrap_or_default();
+        let from = MergedValues::load(&self.opts.input_file, &self.opts.from, additional_options)?;
+        let to = MergedValues::load(
+            self.opts
+                .to_config
+                .as_ref()
+                .unwrap_or(&self.opts.input_file),
+            &self.opts.to,
+            additional_options,
+        )?;
+
+        let differences = diff(&from.values, &to.values);
+        if differences.is_empty() {
+            info!(
+                "values of {} and {} are identical",
+                self.opts.from, self.opts.to
+            );
+        }
+
+        match self.opts.format {
+            OutputFormat::Text => {
+                for d in &differences {
+                    println!("{}", d);
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&differences)?),
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare two sets of values key path by key path
+/// Lists are compared as a whole, since helm replaces them instead of merging.
+fn diff(from: &Value, to: &Value) -> Vec<Difference> {
+    let flat = |v: &Value| {
+        values::flatten(v)
+            .into_iter()
+            .map(|(path, value)| (values::dotted(&path), value))
+            .collect()
+    };
+
+    diff::compare(flat(from), flat(to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::Change;
+    use pretty_assertions::assert_eq;
+
+    fn yaml(s: &str) -> Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn differences_are_listed_by_key_path() {
+        let from = yaml("image: {repository: nginx, tag: '1.24'}\nprobe: {path: /}\nports: [80]");
+        let to = yaml("image: {repository: nginx, tag: '1.25'}\nports: [80, 443]\nreplicas: 3");
+
+        assert_eq!(
+            vec![
+                Difference {
+                    path: "image.tag".to_string(),
+                    change: Change::Changed,
+                    from: Some(Value::from("1.24")),
+                    to: Some(Value::from("1.25")),
+                },
+                Difference {
+                    path: "ports".to_string(),
+                    change: Change::Changed,
+                    from: Some(yaml("[80]")),
+                    to: Some(yaml("[80, 443]")),
+                },
+                Difference {
+                    path: "probe.path".to_string(),
+                    change: Change::Removed,
+                    from: Some(Value::from("/")),
+                    to: None,
+                },
+                Difference {
+                    path: "replicas".to_string(),
+                    change: Change::Added,
+                    from: None,
+                    to: Some(Value::from(3)),
+                },
+            ],
+            diff(&from, &to)
+        );
+    }
+
+    #[test]
+    fn a_mapping_replacing_a_value_is_a_change_of_its_keys() {
+        let from = yaml("resources: {}");
+        let to = yaml("resources: {limits: {cpu: 1}}");
+
+        let changes: Vec<(String, Change)> = diff(&from, &to)
+            .into_iter()
+            .map(|d| (d.path, d.change))
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("resources".to_string(), Change::Removed),
+                ("resources.limits.cpu".to_string(), Change::Added)
+            ],
+            changes
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use crate::config::Config;
+use crate::process;
+use anyhow::anyhow;
+use log::{debug, warn};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Interval in which watched files are checked for modifications
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time without further modifications before a burst of edits is considered complete
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Modification time and size of every watched file
+type Snapshot = BTreeMap<PathBuf, (SystemTime, u64)>;
+
+/// Polls the configuration files and all charts and value files they reference for changes
+///
+/// The set of watched files is re-computed on every poll, so that newly referenced value files
+/// are picked up without restarting.
+pub struct Watcher {
+    /// Configuration files as passed to the templexer
+    input_files: Vec<PathBuf>,
+
+    /// State of the watched files after the last reported change
+    snapshot: Snapshot,
+
+    /// Last error per configuration file whose references could not be resolved
+    errors: BTreeMap<PathBuf, String>,
+}
+
+impl Watcher {
+    /// Start watching the given configuration files and everything they reference
+    pub fn new(input_files: &[PathBuf]) -> Self {
+        let mut watcher = Self {
+            input_files: input_files.to_vec(),
+            snapshot: Default::default(),
+            errors: Default::default(),
+        };
+        watcher.snapshot = watcher.take_snapshot();
+
+        watcher
+    }
+
+    /// Block until watched files change and return the absolute paths of all changed files
+    /// Returns `None` once Ctrl-C was pressed.
+    /// Bursts of modifications are collected until no file changed for the debounce period.
+    pub fn wait_for_changes(&mut self) -> Option<BTreeSet<PathBuf>> {
+        let mut current = loop {
+            std::thread::sleep(POLL_INTERVAL);
+            if process::interrupted() {
+                return None;
+            }
+            let current = self.take_snapshot();
+            if current != self.snapshot {
+                break current;
+            }
+        };
+
+        loop {
+            std::thread::sleep(DEBOUNCE);
+            let next = self.take_snapshot();
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+
+        let changed = Self::changed_files(&self.snapshot, &current);
+        self.snapshot = current;
+
+        Some(changed)
+    }
+
+    /// Compare two snapshots; added, removed and modified files are all considered changed
+    fn changed_files(previous: &Snapshot, current: &Snapshot) -> BTreeSet<PathBuf> {
+        let removed = previous.keys().filter(|p| !current.contains_key(*p));
+        let added_or_modified = current
+            .iter()
+            .filter(|(p, state)| previous.get(*p) != Some(state))
+            .map(|(p, _)| p);
+
+        removed.chain(added_or_modified).cloned().collect()
+    }
+
+    /// Record the modification time and size of every watched file
+    fn take_snapshot(&mut self) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        let mut excluded = vec![];
+
+        for file in &self.input_files {
+            let mut watched = vec![file.clone()];
+
+            // configs may be invalid while being edited; watch what can be found and carry on
+            match Config::load(file).and_then(|cfg| Self::referenced_paths(&cfg, file)) {
+                Ok((paths, output)) => {
+                    self.errors.remove(file);
+                    watched.extend(paths);
+                    excluded.push(output);
+                }
+                Err(err) => {
+                    let message = format!("{:#}", err);
+                    if Self::is_new_error(&mut self.errors, file, &message) {
+                        warn!("can not watch files referenced by {:?}: {}", file, message);
+                    } else {
+                        debug!("can not watch files referenced by {:?}: {}", file, message);
+                    }
+                }
+            }
+
+            for path in watched {
+                Self::record(&mut snapshot, &path);
+            }
+        }
+
+        // manifests are commonly written next to the chart; never react to our own output
+        snapshot.retain(|p, _| !excluded.iter().any(|o| p.starts_with(o)));
+
+        snapshot
+    }
+
+    /// Remember the error of the given file; returns whether it differs from the previous one
+    /// Snapshots are taken on every poll, so an unchanged error must not be reported each time.
+    fn is_new_error(errors: &mut BTreeMap<PathBuf, String>, file: &Path, message: &str) -> bool {
+        if errors.get(file).map(String::as_str) == Some(message) {
+            return false;
+        }
+        errors.insert(file.to_path_buf(), message.to_string());
+
+        true
+    }
+
+    /// Resolve the chart and all value files of the given config, plus its output path
+    /// Paths are resolved relative to the config file without switching the working directory.
+    fn referenced_paths(cfg: &Config, file: &Path) -> anyhow::Result<(Vec<PathBuf>, PathBuf)> {
+        let file = file.canonicalize()?;
+        let base = file
+            .parent()
+            .ok_or_else(|| anyhow!("could not determine base path of {:?}", file))?;
+
+        let values = cfg.deployments.iter().filter_map(|d| d.values.as_ref());
+        let paths = std::iter::once(&cfg.chart)
+            .chain(cfg.values.iter().chain(values).flatten())
+            .map(|p| base.join(p))
+            .collect();
+
+        Ok((paths, base.join(&cfg.output_path)))
+    }
+
+    /// Add the given file, or all files in the given directory, to the snapshot
+    fn record(snapshot: &mut Snapshot, path: &Path) {
+        let path = match path.canonicalize() {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+
+        if path.is_dir() {
+            if let Ok(entries) = std::fs::read_dir(&path) {
+                for entry in entries.flatten() {
+                    Self::record(snapshot, &entry.path());
+                }
+            }
+            return;
+        }
+
+        if let Ok(meta) = path.metadata() {
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            snapshot.insert(path, (modified, meta.len()));
+        }
+    }
+}
+
+/// Summarize how a manifest changed compared to its previous version, e.g. `+3 -1 lines`
+pub fn diff_summary(previous: Option<&str>, current: &str) -> String {
+    let previous = match previous {
+        Some(p) => p,
+        None => return "new".to_string(),
+    };
+
+    if previous == current {
+        return "unchanged".to_string();
+    }
+
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    for line in current.lines() {
+        *counts.entry(line).or_default() += 1;
+    }
+    for line in previous.lines() {
+        *counts.entry(line).or_default() -= 1;
+    }
+
+    let added: isize = counts.values().filter(|c| **c > 0).sum();
+    let removed: isize = -counts.values().filter(|c| **c < 0).sum::<isize>();
+    debug!("manifest diff: {} added, {} removed", added, removed);
+
+    format!("+{} -{} lines", added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn get_snapshot(files: &[(&str, u64)]) -> Snapshot {
+        files
+            .iter()
+            .map(|(f, len)| (PathBuf::from(f), (SystemTime::UNIX_EPOCH, *len)))
+            .collect()
+    }
+
+    #[test]
+    fn added_removed_and_modified_files_are_changed() {
+        let previous = get_snapshot(&[("/a.yaml", 1), ("/b.yaml", 1), ("/c.yaml", 1)]);
+        let current = get_snapshot(&[("/a.yaml", 1), ("/b.yaml", 2), ("/d.yaml", 1)]);
+
+        let changed: Vec<PathBuf> = Watcher::changed_files(&previous, &current)
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            vec![
+                PathBuf::from("/b.yaml"),
+                PathBuf::from("/c.yaml"),
+                PathBuf::from("/d.yaml")
+            ],
+            changed
+        );
+    }
+
+    #[test]
+    fn snapshot_covers_config_chart_and_values() {
+        let watcher = Watcher::new(&[PathBuf::from("tests/data/config_example.yaml")]);
+        let canonical = |p: &str| PathBuf::from(p).canonicalize().unwrap();
+
+        assert!(watcher
+            .snapshot
+            .contains_key(&canonical("tests/data/config_example.yaml")));
+        assert!(watcher
+            .snapshot
+            .contains_key(&canonical("tests/data/nginx-chart/templates/service.yaml")));
+        assert!(watcher
+            .snapshot
+            .contains_key(&canonical("tests/data/nginx-chart/values/prod.yaml")));
+    }
+
+    #[test]
+    fn errors_are_new_until_they_repeat() {
+        let mut errors = BTreeMap::new();
+        let file = Path::new("config.yaml");
+
+        assert!(Watcher::is_new_error(&mut errors, file, "invalid type"));
+        assert!(!Watcher::is_new_error(&mut errors, file, "invalid type"));
+        assert!(Watcher::is_new_error(&mut errors, file, "missing field"));
+        assert!(Watcher::is_new_error(
+            &mut errors,
+            Path::new("other.yaml"),
+            "missing field"
+        ));
+    }
+
+    #[test]
+    fn diff_summary_counts_changed_lines() {
+        assert_eq!("new", diff_summary(None, "a\n"));
+        assert_eq!("unchanged", diff_summary(Some("a\nb\n"), "a\nb\n"));
+        assert_eq!(
+            "+2 -1 lines",
+            diff_summary(Some("a\nb\nc\n"), "a\nc\nd\ne\n")
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use crate::fixture::TempDir;
+use assert_cmd::prelude::*;
+use std::process::Command;
+
+#[test]
+fn stats_and_purge_only_touch_cached_manifests() -> anyhow::Result<()> {
+    let dir = TempDir::new("cache-purge")?;
+    let cache_dir = dir.path();
+    let entry = format!("{}.yaml", "0123abcd".repeat(8));
+    dir.write(".helm-templexer-cache", "")?;
+    dir.write(&entry, "kind: Service\n")?;
+    dir.write("README.md", "not a cache entry\n")?;
+    dir.write("values.yaml", "not a cache entry\n")?;
+
+    Command::cargo_bin("helm-templexer")?
+        .arg("cache")
+        .arg("stats")
+        .arg(cache_dir)
+        .assert()
+        .success()
+        .stdout("entries: 1\nsize: 14 bytes\n");
+
+    Command::cargo_bin("helm-templexer")?
+        .arg("cache")
+        .arg("purge")
+        .arg(cache_dir)
+        .assert()
+        .success();
+
+    assert!(!cache_dir.join(&entry).exists());
+    assert!(cache_dir.join("README.md").exists());
+    assert!(cache_dir.join("values.yaml").exists());
+
+    Ok(())
+}
+
+#[test]
+fn directories_which_are_no_cache_are_refused() -> anyhow::Result<()> {
+    let dir = TempDir::new("cache-refused")?;
+    let config = dir.write("config.yaml", "version: v2\n")?;
+
+    Command::cargo_bin("helm-templexer")?
+        .arg("cache")
+        .arg("purge")
+        .arg(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("is not a render cache"));
+    assert!(config.exists());
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+
+/// Temporary directory for a single test; removed on `drop`, so that failing tests do not leave
+/// it behind
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create an empty directory unique to the given test name
+    pub fn new(name: &str) -> anyhow::Result<Self> {
+        let path =
+            std::env::temp_dir().join(format!("helm-templexer-{}-{}", name, std::process::id()));
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        std::fs::create_dir_all(&path)?;
+
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write a file within the directory, creating its parent directories; returns its path
+    pub fn write(&self, file: &str, content: impl AsRef<[u8]>) -> anyhow::Result<PathBuf> {
+        let path = self.path.join(file);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, content)?;
+
+        Ok(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // failing here would abort a test which is already panicking
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
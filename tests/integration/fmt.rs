@@ -0,0 +1,59 @@
+use crate::fixture::TempDir;
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::process::Command;
+
+#[test]
+fn check_fails_until_the_file_is_formatted() -> anyhow::Result<()> {
+    let dir = TempDir::new("fmt")?;
+    let config = dir.write(
+        "app.yaml",
+        r#"---
+release_name: app
+# shared by all deployments
+chart: ../charts/app
+version: v2
+output_path: manifests
+deployments:
+  - values: [edge.yaml]
+    name: edge
+"#,
+    )?;
+
+    let unformatted = Command::cargo_bin("helm-templexer")?
+        .args(["fmt", "--check"])
+        .arg(&config)
+        .assert();
+    let unchanged = std::fs::read_to_string(&config)?;
+    let formatted = Command::cargo_bin("helm-templexer")?
+        .arg("fmt")
+        .arg(&config)
+        .assert();
+    let content = std::fs::read_to_string(&config)?;
+    let checked = Command::cargo_bin("helm-templexer")?
+        .args(["fmt", "--check"])
+        .arg(&config)
+        .assert();
+
+    unformatted
+        .failure()
+        .stderr(predicate::str::contains("1 file(s) need formatting"));
+    assert!(unchanged.starts_with("---\nrelease_name: app\n"));
+    formatted.success();
+    checked.success();
+    assert_eq!(
+        r#"---
+version: v2
+# shared by all deployments
+chart: ../charts/app
+release_name: app
+output_path: manifests
+deployments:
+  - name: edge
+    values: [edge.yaml]
+"#,
+        content
+    );
+
+    Ok(())
+}
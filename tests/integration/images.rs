@@ -0,0 +1,65 @@
+use crate::fixture::TempDir;
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Write a config whose output path points to the manifests in `tests/data/rendered_manifests`
+fn config(dir: &TempDir) -> anyhow::Result<PathBuf> {
+    let output_path = std::env::current_dir()?.join("tests/data/rendered_manifests");
+    dir.write(
+        "config.yaml",
+        format!(
+            r#"---
+version: v2
+chart: nginx-chart
+release_name: my-app
+output_path: {}
+deployments:
+  - name: edge-eu-w4
+"#,
+            output_path.display()
+        ),
+    )
+}
+
+#[test]
+fn images_are_listed_per_deployment() -> anyhow::Result<()> {
+    let dir = TempDir::new("images-grouped")?;
+    let config = config(&dir)?;
+
+    let assert = Command::cargo_bin("helm-templexer")?
+        .arg("images")
+        .arg(&config)
+        .assert();
+
+    assert.success().stdout(format!(
+        "{}: edge-eu-w4\n  nginx:latest\n",
+        config.display()
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn floating_tags_can_be_forbidden() -> anyhow::Result<()> {
+    let dir = TempDir::new("images-floating")?;
+    let config = config(&dir)?;
+
+    let assert = Command::cargo_bin("helm-templexer")?
+        .arg("images")
+        .arg("--unique")
+        .arg("--forbid-floating-tags")
+        .arg(&config)
+        .assert();
+
+    assert
+        .failure()
+        .stdout("nginx:latest\n")
+        .stderr(predicate::str::contains(
+            "edge-eu-w4: image nginx:latest uses a floating tag",
+        ))
+        .stderr(predicate::str::contains("1 image(s) are not pinned"));
+
+    Ok(())
+}
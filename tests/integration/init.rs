@@ -0,0 +1,55 @@
+use crate::fixture::TempDir;
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::process::Command;
+
+#[test]
+fn config_is_scaffolded_from_values_files() -> anyhow::Result<()> {
+    let dir = TempDir::new("init")?;
+    let config = dir.path().join("nginx.yaml");
+
+    Command::cargo_bin("helm-templexer")?
+        .arg("init")
+        .arg("--namespace=apps")
+        .arg(format!("--output={}", config.display()))
+        .arg("tests/data/nginx-chart")
+        .assert()
+        .success();
+
+    let scaffolded = std::fs::read_to_string(&config)?;
+    let again = Command::cargo_bin("helm-templexer")?
+        .arg("init")
+        .arg(format!("--output={}", config.display()))
+        .arg("tests/data/nginx-chart")
+        .assert();
+    let validated = Command::cargo_bin("helm-templexer")?
+        .arg("validate")
+        .arg(&config)
+        .assert();
+
+    validated.success();
+    again
+        .failure()
+        .stderr(predicate::str::contains("use --force to overwrite it"));
+
+    assert!(scaffolded.starts_with("---\nversion: v2\n"));
+    assert!(scaffolded.contains("namespace: apps\nrelease_name: nginx-chart\n"));
+    assert!(
+        scaffolded.contains("nginx-chart/values/default.yaml\"\ndeployments:\n  - name: edge\n")
+    );
+    assert!(scaffolded.contains("  - name: prod-eu-w4\n    values:\n"));
+
+    Command::cargo_bin("helm-templexer")?
+        .arg("init")
+        .arg("--force")
+        .arg(format!("--output={}", config.display()))
+        .arg("tests/data/nginx-chart")
+        .assert()
+        .success();
+    let entries: Vec<_> = std::fs::read_dir(dir.path())?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(vec![config.file_name().unwrap().to_owned()], entries);
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use crate::fixture::TempDir;
+use assert_cmd::prelude::*;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Write a config whose output path points to the manifests in `tests/data/rendered_manifests`
+fn config(dir: &TempDir) -> anyhow::Result<PathBuf> {
+    let output_path = std::env::current_dir()?.join("tests/data/rendered_manifests");
+    dir.write(
+        "config.yaml",
+        format!(
+            r#"---
+version: v2
+chart: nginx-chart
+release_name: my-app
+output_path: {}
+deployments:
+  - name: edge-eu-w4
+  - name: prod-eu-w4
+"#,
+            output_path.display()
+        ),
+    )
+}
+
+#[test]
+fn resources_of_existing_manifests_are_listed() -> anyhow::Result<()> {
+    let dir = TempDir::new("inventory-table")?;
+    let config = config(&dir)?;
+
+    let output = Command::cargo_bin("helm-templexer")?
+        .arg("inventory")
+        .arg("--kind=service")
+        .arg(&config)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let table = String::from_utf8(output)?;
+    let lines: Vec<&str> = table.lines().collect();
+
+    assert_eq!(2, lines.len());
+    assert!(lines[0].starts_with("CONFIG"));
+    assert!(lines[0].ends_with("APIVERSION  KIND     NAMESPACE  NAME"));
+    assert!(lines[1].contains(" edge-eu-w4  "));
+    assert!(lines[1].ends_with("v1          Service  -          my-app-nginx-chart"));
+
+    Ok(())
+}
+
+#[test]
+fn resources_are_filtered_by_name() -> anyhow::Result<()> {
+    let dir = TempDir::new("inventory-json")?;
+    let config = config(&dir)?;
+
+    let output = Command::cargo_bin("helm-templexer")?
+        .arg("inventory")
+        .arg("--format=json")
+        .arg("--name=^my-app")
+        .arg(&config)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let resources: serde_json::Value = serde_json::from_slice(&output)?;
+    let kinds: Vec<&str> = resources
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["kind"].as_str().unwrap())
+        .collect();
+
+    assert_eq!(vec!["ServiceAccount", "Service", "Deployment"], kinds);
+    assert_eq!("edge-eu-w4", resources[0]["deployment"]);
+    assert_eq!("apps/v1", resources[2]["api_version"]);
+
+    Ok(())
+}
@@ -1,2 +1,13 @@
+mod cache;
+mod fixture;
+mod fmt;
+mod images;
+mod init;
+mod inventory;
+mod manifest_diff;
+mod migrate;
+mod prune;
 mod render;
+mod uses;
 mod validate;
+mod values;
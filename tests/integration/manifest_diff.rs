@@ -0,0 +1,109 @@
+use crate::fixture::TempDir;
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Write a config with two deployments and a previously rendered manifest for each of them
+fn config(dir: &TempDir) -> anyhow::Result<PathBuf> {
+    for (deployment, release, replicas, host) in [
+        ("stage", "app-stage", 1, "stage.example.com"),
+        ("prod", "app-prod", 3, "example.com"),
+    ] {
+        dir.write(
+            &format!("manifests/{}/{}/manifest.yaml", deployment, release),
+            format!(
+                r#"---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: {release}-web
+spec:
+  replicas: {replicas}
+---
+apiVersion: networking.k8s.io/v1
+kind: Ingress
+metadata:
+  name: {release}-web
+spec:
+  rules:
+    - host: {host}
+"#,
+                release = release,
+                replicas = replicas,
+                host = host
+            ),
+        )?;
+    }
+
+    dir.write(
+        "config.yaml",
+        r#"---
+version: v2
+chart: chart
+release_name: app
+output_path: manifests
+deployments:
+  - name: stage
+    release_name: app-stage
+  - name: prod
+    release_name: app-prod
+"#,
+    )
+}
+
+#[test]
+fn manifests_of_two_deployments_are_compared() -> anyhow::Result<()> {
+    let dir = TempDir::new("manifest-diff-changed")?;
+    let config = config(&dir)?;
+
+    let assert = Command::cargo_bin("helm-templexer")?
+        .arg("manifest-diff")
+        .arg(&config)
+        .arg("stage")
+        .arg("prod")
+        .assert();
+
+    assert.success().stdout(
+        "~ Deployment/<release>-web\n    \
+           ~ spec.replicas: 1 -> 3\n\
+         ~ Ingress/<release>-web\n    \
+           ~ spec.rules[0].host: \"stage.example.com\" -> \"example.com\"\n",
+    );
+
+    Ok(())
+}
+
+#[test]
+fn expected_differences_are_ignored() -> anyhow::Result<()> {
+    let dir = TempDir::new("manifest-diff-ignored")?;
+    let config = config(&dir)?;
+
+    let assert = Command::cargo_bin("helm-templexer")?
+        .arg("manifest-diff")
+        .arg("--exit-code")
+        .arg("--ignore=Deployment:spec.replicas")
+        .arg(&config)
+        .arg("stage")
+        .arg("prod")
+        .assert();
+
+    assert
+        .failure()
+        .stdout(predicate::str::contains("Deployment").not())
+        .stderr(predicate::str::contains("1 resource(s) differ"));
+
+    Command::cargo_bin("helm-templexer")?
+        .arg("manifest-diff")
+        .arg("--exit-code")
+        .arg("--ignore=spec.replicas")
+        .arg("--ignore=spec.rules[*].host")
+        .arg(&config)
+        .arg("stage")
+        .arg("prod")
+        .assert()
+        .success()
+        .stdout("");
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use crate::fixture::TempDir;
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::process::Command;
+
+#[test]
+fn v1_config_is_migrated_in_place() -> anyhow::Result<()> {
+    let repo = TempDir::new("migrate")?;
+    repo.write("charts/app/Chart.yaml", "name: app\n")?;
+    repo.write("charts/app/values/edge.yaml", "")?;
+
+    let config = repo.write(
+        "configs/app.yaml",
+        r#"---
+version: v1
+# paths used to be relative to the repository root
+chart: charts/app
+release_name: app
+output_path: manifests
+deployments:
+  - name: edge
+    values:
+      - charts/app/values/edge.yaml
+"#,
+    )?;
+
+    let migrated = Command::cargo_bin("helm-templexer")?
+        .arg("migrate")
+        .arg(format!("--base-dir={}", repo.path().display()))
+        .arg(&config)
+        .assert();
+    let content = std::fs::read_to_string(&config)?;
+    let validated = Command::cargo_bin("helm-templexer")?
+        .arg("validate")
+        .arg(&config)
+        .assert();
+
+    migrated.success().stderr(predicate::str::contains(
+        "app.yaml:6: manifests are now written to",
+    ));
+    validated.success();
+    assert_eq!(
+        r#"---
+version: v2
+# paths used to be relative to the repository root
+chart: ../charts/app
+release_name: app
+output_path: ../manifests
+deployments:
+  - name: edge
+    values:
+      - ../charts/app/values/edge.yaml
+"#,
+        content
+    );
+
+    Ok(())
+}
+
+#[test]
+fn configs_which_can_not_be_loaded_after_migration_are_left_untouched() -> anyhow::Result<()> {
+    let repo = TempDir::new("migrate-unloadable")?;
+    let source = "---\nversion: v1\nchart: charts/app\nrelease_name: app\noutput_path: manifests\n";
+    let config = repo.write("app.yaml", source)?;
+
+    Command::cargo_bin("helm-templexer")?
+        .arg("migrate")
+        .arg(&config)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "migrated configuration can not be loaded",
+        ));
+    assert_eq!(source, std::fs::read_to_string(&config)?);
+
+    Ok(())
+}
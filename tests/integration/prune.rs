@@ -0,0 +1,68 @@
+use crate::fixture::TempDir;
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Write a config with a single deployment, next to output directories of removed deployments
+fn config(dir: &TempDir) -> anyhow::Result<PathBuf> {
+    for (deployment, owned) in [("edge", true), ("renamed", true), ("hand-written", false)] {
+        let output = format!("manifests/{}/my-app", deployment);
+        dir.write(&format!("{}/manifest.yaml", output), "---\n")?;
+        if owned {
+            dir.write(&format!("{}/.helm-templexer", output), "")?;
+        }
+    }
+
+    dir.write(
+        "config.yaml",
+        r#"---
+version: v2
+chart: chart
+release_name: my-app
+output_path: manifests
+deployments:
+  - name: edge
+"#,
+    )
+}
+
+#[test]
+fn orphaned_output_directories_are_listed() -> anyhow::Result<()> {
+    let dir = TempDir::new("prune-dry-run")?;
+    let config = config(&dir)?;
+    let manifests = dir.path().join("manifests");
+
+    let assert = Command::cargo_bin("helm-templexer")?
+        .arg("prune")
+        .arg("--dry-run")
+        .arg(&config)
+        .assert();
+
+    let renamed = manifests.canonicalize()?.join("renamed").join("my-app");
+    assert.success().stdout(format!("{}\n", renamed.display()));
+    assert!(renamed.exists());
+    Ok(())
+}
+
+#[test]
+fn only_owned_output_directories_are_removed() -> anyhow::Result<()> {
+    let dir = TempDir::new("prune-remove")?;
+    let config = config(&dir)?;
+    let manifests = dir.path().join("manifests");
+
+    Command::cargo_bin("helm-templexer")?
+        .arg("prune")
+        .arg(&config)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "removing orphaned output directory",
+        ));
+
+    let exists = |d: &str| manifests.join(d).exists();
+    let remaining = (exists("edge"), exists("renamed"), exists("hand-written"));
+
+    assert_eq!((true, false, true), remaining);
+    Ok(())
+}
@@ -7,7 +7,7 @@ use std::io::Write;
 use std::path::PathBuf;
 use std::process::Command;
 
-const BIN_NAME: &'static str = env!("CARGO_PKG_NAME");
+const BIN_NAME: &str = env!("CARGO_PKG_NAME");
 
 struct Config {
     temp_dir: PathBuf,
@@ -56,6 +56,7 @@ deployments:
             .write(true)
             .read(true)
             .create(true)
+            .truncate(true)
             .open(&path)
             .unwrap();
 
@@ -106,30 +107,24 @@ fn render_config_example() -> anyhow::Result<()> {
     );
 
     // assert that all the deployment directories exist
-    assert_eq!(PathBuf::from(&manifests_folder).exists(), true);
-    assert_eq!(PathBuf::from(&edge_manifests_folder).exists(), true);
-    assert_eq!(PathBuf::from(stage_manifest_folder).exists(), true);
-    assert_eq!(PathBuf::from(prod_manifest_folder).exists(), true);
-    assert_eq!(PathBuf::from(next_edge_manifest_folder).exists(), false);
+    assert!(PathBuf::from(&manifests_folder).exists());
+    assert!(PathBuf::from(&edge_manifests_folder).exists());
+    assert!(PathBuf::from(stage_manifest_folder).exists());
+    assert!(PathBuf::from(prod_manifest_folder).exists());
+    assert!(!PathBuf::from(next_edge_manifest_folder).exists());
 
     // asert that the release name override for prod-eu-e4 worked
-    assert_eq!(
-        PathBuf::from(format!(
-            "{}/prod-eu-w4/my-app-prod-eu-w4/manifest.yaml",
-            manifests_folder
-        ))
-        .exists(),
-        true
-    );
-
-    assert_eq!(
-        PathBuf::from(format!(
-            "{}/edge-eu-w4/my-app/manifest.yaml",
-            manifests_folder
-        ))
-        .exists(),
-        true
-    );
+    assert!(PathBuf::from(format!(
+        "{}/prod-eu-w4/my-app-prod-eu-w4/manifest.yaml",
+        manifests_folder
+    ))
+    .exists());
+
+    assert!(PathBuf::from(format!(
+        "{}/edge-eu-w4/my-app/manifest.yaml",
+        manifests_folder
+    ))
+    .exists());
 
     let edge_rendered_output = format!(
         "{}/manifests/edge-eu-w4/my-app/manifest.yaml",
@@ -139,7 +134,7 @@ fn render_config_example() -> anyhow::Result<()> {
     let mut edge_deployment_yaml = std::fs::File::open(edge_rendered_output)?;
     let mut contents = "".to_string();
     edge_deployment_yaml.read_to_string(&mut contents)?;
-    assert_eq!(contents.contains("image: \"nginx:latest\""), true);
+    assert!(contents.contains("image: \"nginx:latest\""));
 
     assert_eq!(
         contents,
@@ -246,3 +241,132 @@ fn errors_are_logged() -> anyhow::Result<()> {
     ));
     Ok(())
 }
+
+#[test]
+fn render_to_stdout() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+    let config = Config::new()?;
+
+    cmd.arg("render").arg("--stdout").arg(&config.path);
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stream = String::from_utf8(output)?;
+
+    // deployments are written in order of appearance, each with a header
+    let edge = stream
+        .find("# helm-templexer: config=")
+        .expect("missing header");
+    let prod = stream
+        .find("deployment=prod-eu-w4")
+        .expect("missing prod-eu-w4");
+    assert!(stream.starts_with("---\n"));
+    assert!(stream[edge..].starts_with(&format!(
+        "# helm-templexer: config={} deployment=edge-eu-w4\n",
+        config.path.display()
+    )));
+    assert!(edge < prod);
+    assert!(!stream.contains("deployment=next-edge-eu-w4"));
+
+    // nothing is written to the output path
+    assert!(!config.temp_dir.join("manifests").exists());
+
+    Ok(())
+}
+
+#[test]
+fn report_is_written() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+    let config = Config::new()?;
+    let report = config.temp_dir.join("report.json");
+
+    cmd.arg("render")
+        .arg("--report")
+        .arg(&report)
+        .arg(&config.path);
+
+    cmd.assert().success();
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report)?)?;
+    let entries = report["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 4);
+
+    let next_edge = entries
+        .iter()
+        .find(|e| e["deployment"] == "next-edge-eu-w4")
+        .unwrap();
+    assert_eq!(next_edge["status"], "disabled");
+    assert_eq!(next_edge["skip_reason"], "deployment is disabled");
+
+    let edge = entries
+        .iter()
+        .find(|e| e["deployment"] == "edge-eu-w4")
+        .unwrap();
+    assert_eq!(edge["status"], "rendered");
+    assert_eq!(edge["argv"][0], "helm");
+    assert_eq!(edge["sha256"].as_str().unwrap().len(), 64);
+    assert!(edge["resource_count"].as_u64().unwrap() > 0);
+    assert!(edge["output_path"]
+        .as_str()
+        .unwrap()
+        .ends_with("manifests/edge-eu-w4/my-app/manifest.yaml"));
+
+    Ok(())
+}
+
+#[test]
+fn junit_report_is_written() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+    let config = Config::new()?;
+    let junit = config.temp_dir.join("junit.xml");
+
+    cmd.arg("render")
+        .arg("--junit")
+        .arg(&junit)
+        .arg(&config.path);
+
+    cmd.assert().success();
+
+    let xml = std::fs::read_to_string(&junit)?;
+    assert!(xml.contains(r#"tests="4" failures="0" skipped="1""#));
+    assert!(xml.contains(r#"<testcase name="edge-eu-w4""#));
+    assert!(xml.contains(r#"<skipped message="deployment is disabled"/>"#));
+
+    Ok(())
+}
+
+#[test]
+fn manifests_without_schema_are_rejected() -> anyhow::Result<()> {
+    let config = Config::new()?;
+    let schema_dir = config.temp_dir.join("schemas");
+    std::fs::create_dir_all(&schema_dir)?;
+
+    Command::cargo_bin(BIN_NAME)?
+        .arg("render")
+        .arg("--validate-manifests")
+        .arg("--kube-version=1.29")
+        .arg("--schema-dir")
+        .arg(&schema_dir)
+        .arg(&config.path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "manifest of deployment edge-eu-w4 does not match the Kubernetes 1.29 schemas",
+        ))
+        .stderr(predicate::str::contains(
+            "Service my-app-nginx-chart (v1): no schema found; searched",
+        ));
+    assert!(!config.temp_dir.join("manifests").exists());
+
+    Command::cargo_bin(BIN_NAME)?
+        .arg("render")
+        .arg("--validate-manifests")
+        .arg("--kube-version=1.29")
+        .arg("--ignore-missing-schemas")
+        .arg("--schema-dir")
+        .arg(&schema_dir)
+        .arg(&config.path)
+        .assert()
+        .success();
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::process::Command;
+
+#[test]
+fn values_file_usages_are_listed() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin("helm-templexer")?;
+
+    cmd.current_dir("tests/data")
+        .arg("uses")
+        .arg("nginx-chart/values/prod.yaml")
+        .arg("config_example.yaml");
+
+    cmd.assert()
+        .success()
+        .stdout("config_example.yaml: prod-eu-w4 (values: nginx-chart/values/prod.yaml)\n");
+
+    Ok(())
+}
+
+#[test]
+fn chart_usages_are_listed_as_json() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin("helm-templexer")?;
+
+    cmd.current_dir("tests/data")
+        .arg("uses")
+        .arg("--format=json")
+        .arg("nginx-chart/templates/deployment.yaml")
+        .arg("config_example.yaml");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let usages: serde_json::Value = serde_json::from_slice(&output)?;
+
+    assert_eq!(4, usages.as_array().unwrap().len());
+    assert_eq!("next-edge-eu-w4", usages[1]["deployment"]);
+    assert_eq!(false, usages[1]["enabled"]);
+    assert_eq!("chart", usages[1]["reference"]);
+
+    Ok(())
+}
+
+#[test]
+fn path_does_not_exist() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin("helm-templexer")?;
+
+    cmd.current_dir("tests/data")
+        .arg("uses")
+        .arg("does-not-exist.yaml")
+        .arg("config_example.yaml");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        r#"File "does-not-exist.yaml" does not exist or is not readable"#,
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn config_files_are_required() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin("helm-templexer")?;
+
+    cmd.current_dir("tests/data")
+        .arg("uses")
+        .arg("nginx-chart/values/prod.yaml");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("<input-files>"));
+
+    Ok(())
+}
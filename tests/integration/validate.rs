@@ -1,3 +1,4 @@
+use crate::fixture::TempDir;
 use assert_cmd::prelude::*;
 use predicates::prelude::*;
 use std::process::Command;
@@ -54,3 +55,101 @@ fn validate_accepts_multiple_files() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn junit_report_contains_every_check() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin("helm-templexer")?;
+    let dir = TempDir::new("validate-junit")?;
+    let junit = dir.path().join("junit.xml");
+
+    cmd.current_dir("tests/data")
+        .arg("validate")
+        .arg("--junit")
+        .arg(&junit)
+        .arg("config_example.yaml")
+        .arg("config_chart_does_not_exist.yaml");
+    cmd.assert().failure();
+
+    let xml = std::fs::read_to_string(&junit)?;
+
+    assert!(
+        xml.contains(r#"<testsuites name="helm-templexer" tests="16" failures="2" skipped="0">"#)
+    );
+    assert!(xml.contains(r#"<testsuite name="config_example.yaml" tests="8" failures="0""#));
+    assert!(xml
+        .contains(r#"<testcase name="chart exists" classname="config_chart_does_not_exist.yaml""#));
+    assert!(xml.contains(r#"<failure message="invalid schema version used"#));
+    assert!(
+        xml.contains(r#"<failure message="Chart &quot;this-does-not-exist&quot; does not exist"#)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn junit_report_contains_files_after_an_invalid_one() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin("helm-templexer")?;
+    let dir = TempDir::new("validate-junit-all")?;
+    let junit = dir.path().join("junit.xml");
+
+    cmd.current_dir("tests/data")
+        .arg("validate")
+        .arg("--junit")
+        .arg(&junit)
+        .arg("config_chart_does_not_exist.yaml")
+        .arg("config_example.yaml");
+    cmd.assert().failure();
+
+    let xml = std::fs::read_to_string(&junit)?;
+
+    assert!(xml.contains(r#"<testsuite name="config_chart_does_not_exist.yaml""#));
+    assert!(xml.contains(r#"<testsuite name="config_example.yaml" tests="8" failures="0""#));
+
+    Ok(())
+}
+
+#[test]
+fn github_annotations_point_to_the_invalid_field() -> anyhow::Result<()> {
+    let mut cmd = Command::cargo_bin("helm-templexer")?;
+
+    cmd.current_dir("tests/data")
+        .arg("validate")
+        .arg("--format=github")
+        .arg("config_chart_does_not_exist.yaml")
+        .arg("config_example.yaml");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        r#"::error file=config_chart_does_not_exist.yaml,line=4,col=1::Chart "this-does-not-exist" does not exist or is not readable"#,
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn sarif_log_is_written_to_a_file() -> anyhow::Result<()> {
+    let dir = TempDir::new("validate-sarif")?;
+    let sarif = dir.path().join("validation.sarif");
+
+    Command::cargo_bin("helm-templexer")?
+        .current_dir("tests/data")
+        .arg("validate")
+        .arg("--format=sarif")
+        .arg("--sarif")
+        .arg(&sarif)
+        .arg("config_chart_does_not_exist.yaml")
+        .arg("config_example.yaml")
+        .assert()
+        .failure()
+        .stdout("");
+
+    let log: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&sarif)?)?;
+    let results = log["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(2, results.len());
+    for result in results {
+        assert_eq!(
+            "config_chart_does_not_exist.yaml",
+            result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"]
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::fixture::TempDir;
+use assert_cmd::prelude::*;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Write a chart, layered values files and a config using them to a temporary directory
+fn config(dir: &TempDir) -> anyhow::Result<PathBuf> {
+    dir.write(
+        "chart/values.yaml",
+        "replicas: 1\nimage:\n  repository: nginx\n  tag: stable\nprobe:\n  path: /\n",
+    )?;
+    dir.write("base.yaml", "image:\n  tag: \"1.24\"\n")?;
+    dir.write("prod.yaml", "replicas: 3\nprobe: null\n")?;
+
+    dir.write(
+        "config.yaml",
+        r#"---
+version: v2
+chart: chart
+release_name: app
+output_path: manifests
+values:
+  - base.yaml
+additional_options:
+  - "--set replicas=2"
+deployments:
+  - name: prod
+    values:
+      - prod.yaml
+    additional_options:
+      - "--set-string image.tag=1.25"
+  - name: stage
+    additional_options:
+      - "--set probe.path=/healthz"
+"#,
+    )
+}
+
+#[test]
+fn values_are_merged_in_helm_precedence() -> anyhow::Result<()> {
+    let dir = TempDir::new("values-merged")?;
+    let config = config(&dir)?;
+
+    let output = Command::cargo_bin("helm-templexer")?
+        .arg("values")
+        .arg("--deployment=prod")
+        .arg(&config)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let values: serde_yaml::Value = serde_yaml::from_slice(&output)?;
+    let expected: serde_yaml::Value =
+        serde_yaml::from_str("replicas: 2\nimage:\n  repository: nginx\n  tag: \"1.25\"\n")?;
+    assert_eq!(expected, values);
+
+    Ok(())
+}
+
+#[test]
+fn values_are_annotated_with_their_source() -> anyhow::Result<()> {
+    let dir = TempDir::new("values-sources")?;
+    let config = config(&dir)?;
+
+    let output = Command::cargo_bin("helm-templexer")?
+        .arg("values")
+        .arg("--deployment=prod")
+        .arg("--show-sources")
+        .arg(&config)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    assert_eq!(
+        "replicas: 2  # --set replicas=2\n\
+         image:\n  \
+           repository: nginx  # chart/values.yaml\n  \
+           tag: \"1.25\"  # --set-string image.tag=1.25\n",
+        String::from_utf8(output)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn values_of_two_deployments_are_compared() -> anyhow::Result<()> {
+    let dir = TempDir::new("values-diff")?;
+    let config = config(&dir)?;
+
+    let assert = Command::cargo_bin("helm-templexer")?
+        .arg("values-diff")
+        .arg(&config)
+        .arg("stage")
+        .arg("prod")
+        .assert();
+
+    assert
+        .success()
+        .stdout("~ image.tag: \"1.24\" -> \"1.25\"\n- probe.path: \"/healthz\"\n");
+
+    Ok(())
+}